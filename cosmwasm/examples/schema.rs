@@ -1,9 +1,13 @@
 use std::env::current_dir;
 use std::fs::create_dir_all;
 
-use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
+use cosmwasm_schema::{export_schema, export_schema_with_title, remove_schemas, schema_for};
 
-use cosmwasm::msg::{ContentResponse, HandleMsg, InitMsg, QueryMsg};
+use cosmwasm::helpers::ContentTrackerContract;
+use cosmwasm::msg::{
+    AskPriceResponse, ContentResponse, HandleMsg, HarbergerResponse, InitMsg, QueryMsg,
+    RouteHookMsg, SubscribersResponse, SudoMsg, TaxCollectedResponse,
+};
 use cosmwasm::state::Config;
 
 fn main() {
@@ -15,6 +19,30 @@ fn main() {
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(SudoMsg), &out_dir);
+    export_schema(&schema_for!(RouteHookMsg), &out_dir);
     export_schema(&schema_for!(Config), &out_dir);
+    export_schema(&schema_for!(ContentTrackerContract), &out_dir);
     export_schema(&schema_for!(ContentResponse), &out_dir);
+    export_schema(&schema_for!(AskPriceResponse), &out_dir);
+    export_schema(&schema_for!(HarbergerResponse), &out_dir);
+    export_schema(&schema_for!(TaxCollectedResponse), &out_dir);
+    export_schema(&schema_for!(SubscribersResponse), &out_dir);
+
+    // Query responses, named after the query they are returned from
+    export_schema_with_title(
+        &mut schema_for!(Option<ContentResponse>),
+        &out_dir,
+        "GetRouteResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(Option<AskPriceResponse>),
+        &out_dir,
+        "CurrentAskPriceResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(Option<HarbergerResponse>),
+        &out_dir,
+        "HarbergerInfoResponse",
+    );
 }
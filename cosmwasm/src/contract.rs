@@ -1,43 +1,91 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env, HandleResponse, HumanAddr,
-    InitResponse, MessageInfo, StdResult,
+    to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo,
+    Response, StdResult, Storage, Uint128, WasmMsg,
 };
 
-use crate::state::{config, config_read, Config};
+use crate::state::{
+    config, config_read, subscribers, subscribers_read, tax_ledger, tax_ledger_read, Config,
+    MAX_SUBSCRIBERS,
+};
 use crate::{
     error::ContractError,
-    state::{resolver, resolver_read},
+    state::{listings, listings_read, resolver, resolver_read},
 };
 use crate::{
-    msg::{ContentResponse, HandleMsg, InitMsg, QueryMsg},
-    state::ContentRecord,
+    msg::{
+        AskPriceResponse, ContentResponse, HandleMsg, HarbergerResponse, InitMsg, PurchaseItem,
+        QueryMsg, RouteHookMsg, SubscribersResponse, SudoMsg, TaxCollectedResponse,
+    },
+    state::{ContentRecord, HarbergerTax, Listing},
 };
 
 /// Initializes new contract.
-pub fn init(
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
     _msg: InitMsg,
-) -> Result<InitResponse, ContractError> {
+) -> Result<Response, ContractError> {
     let state = Config {
-        owner: deps.api.canonical_address(&info.sender)?,
+        owner: deps.api.addr_canonicalize(info.sender.as_str())?,
+        harberger_tax_rate: Decimal::zero(),
+        paused: false,
     };
     config(deps.storage).save(&state)?;
 
-    Ok(InitResponse::default())
+    Ok(Response::default())
 }
 
 /// Handle incoming messages.
-pub fn handle(
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: HandleMsg,
-) -> Result<HandleResponse, ContractError> {
+) -> Result<Response, ContractError> {
     match msg {
         HandleMsg::Purchase { route, content } => try_purchase(deps, env, info, route, content),
-        HandleMsg::Withdraw {} => try_withdraw(deps, env, info),
+        HandleMsg::PurchaseBatch { items } => try_purchase_batch(deps, env, info, items),
+        HandleMsg::Withdraw {} => try_withdraw(deps, info),
+        HandleMsg::ListRoute {
+            route,
+            start_price,
+            end_price,
+            duration,
+        } => try_list_route(deps, env, info, route, start_price, end_price, duration),
+        HandleMsg::HarbergerPurchase {
+            route,
+            content,
+            assessed_price,
+        } => try_harberger_purchase(deps, env, info, route, content, assessed_price),
+        HandleMsg::HarbergerTopUp { route } => try_harberger_top_up(deps, env, info, route),
+        HandleMsg::HarbergerReprice {
+            route,
+            assessed_price,
+        } => try_harberger_reprice(deps, env, info, route, assessed_price),
+        HandleMsg::SetTaxRate { rate } => try_set_tax_rate(deps, info, rate),
+        HandleMsg::Takedown { route } => try_takedown(deps, env, info, route),
+        HandleMsg::SetPaused { paused } => try_set_paused(deps, info, paused),
+        HandleMsg::Subscribe { contract } => try_subscribe(deps, info, contract),
+        HandleMsg::Unsubscribe { contract } => try_unsubscribe(deps, info, contract),
+    }
+}
+
+/// Handle privileged messages from the chain. These skip the owner check but otherwise apply
+/// the same state transitions as the matching owner messages.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::ForceTakedown { route } => takedown_route(deps, env, route),
+        SudoMsg::SetConfig {
+            owner,
+            harberger_tax_rate,
+        } => update_config(deps, owner, harberger_tax_rate),
+        SudoMsg::Pause { paused } => set_paused(deps, paused),
     }
 }
 
@@ -47,16 +95,121 @@ fn try_purchase(
     info: MessageInfo,
     route: String,
     content: String,
-) -> Result<HandleResponse, ContractError> {
-    let resolved = resolver_read(deps.storage).may_load(route.as_bytes())?;
+) -> Result<Response, ContractError> {
+    if config_read(deps.storage).load()?.paused {
+        return Err(ContractError::Paused {});
+    }
 
-    let (new_price, messages) = if let Some(existing) = resolved {
-        // Route is taken, check if sent funds is greater before replacing
-        let sent = info
-            .sent_funds
-            .into_iter()
-            .find(|coin| coin.denom == existing.price.denom)
+    let (price, refund) = purchase_route(
+        deps.storage,
+        &env,
+        &info.sender,
+        &route,
+        content,
+        info.funds,
+    )?;
+
+    let mut messages = match refund {
+        Some((owner, price)) => send_tokens(&owner, vec![price])?,
+        None => vec![],
+    };
+    messages.extend(notify_subscribers(deps.storage, route, info.sender, price)?);
+
+    Ok(Response::new().add_messages(messages))
+}
+
+fn try_purchase_batch(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    items: Vec<PurchaseItem>,
+) -> Result<Response, ContractError> {
+    if config_read(deps.storage).load()?.paused {
+        return Err(ContractError::Paused {});
+    }
+
+    // Sent funds must cover the bids of all items, any excess is returned to the sender.
+    let mut required: Vec<Coin> = Vec::new();
+    for item in items.iter() {
+        match required
+            .iter_mut()
+            .find(|coin| coin.denom == item.amount.denom)
+        {
+            Some(coin) => coin.amount += item.amount.amount,
+            None => required.push(item.amount.clone()),
+        }
+    }
+    let mut excess = info.funds;
+    for total in required {
+        let sent = excess
+            .iter_mut()
+            .find(|coin| coin.denom == total.denom)
             .ok_or(ContractError::InvalidCoins {})?;
+        if sent.amount < total.amount {
+            return Err(ContractError::InsufficientFunds {
+                sent: sent.amount,
+                required: total.amount,
+            });
+        }
+        sent.amount -= total.amount;
+    }
+
+    let mut refunds = Vec::new();
+    let mut notifications = Vec::new();
+    for item in items {
+        let (price, refund) = purchase_route(
+            deps.storage,
+            &env,
+            &info.sender,
+            &item.route,
+            item.content,
+            vec![item.amount],
+        )?;
+        if let Some((owner, price)) = refund {
+            add_refund(&mut refunds, owner, price);
+        }
+        notifications.extend(notify_subscribers(
+            deps.storage,
+            item.route,
+            info.sender.clone(),
+            price,
+        )?);
+    }
+    for coin in excess {
+        if !coin.amount.is_zero() {
+            add_refund(&mut refunds, info.sender.clone(), coin);
+        }
+    }
+
+    let mut messages = Vec::with_capacity(refunds.len());
+    for (owner, amount) in refunds {
+        messages.extend(send_tokens(&owner, amount)?);
+    }
+    messages.extend(notifications);
+
+    Ok(Response::new().add_messages(messages))
+}
+
+/// Applies a purchase of a route, returning the price paid along with the previous owner and
+/// the price to refund them if the route was outbid.
+fn purchase_route(
+    storage: &mut dyn Storage,
+    env: &Env,
+    sender: &Addr,
+    route: &str,
+    content: String,
+    sent_funds: Vec<Coin>,
+) -> Result<(Coin, Option<(Addr, Coin)>), ContractError> {
+    let resolved = resolver_read(storage).may_load(route.as_bytes())?;
+    let listing = listings_read(storage).may_load(route.as_bytes())?;
+
+    let (new_price, refund) = if let Some(existing) = resolved {
+        if existing.harberger.is_some() {
+            return Err(ContractError::HarbergerRoute {});
+        }
+
+        // Route is taken, check if sent funds is greater before replacing
+        let sent = find_coin(sent_funds, &existing.price.denom)?;
 
         if sent.amount <= existing.price.amount {
             return Err(ContractError::InsufficientFunds {
@@ -66,56 +219,472 @@ fn try_purchase(
         }
 
         // Refund existing owner original price paid.
-        let messages = send_tokens(&env.contract.address, &existing.owner, vec![existing.price])?;
+        (sent, Some((existing.owner, existing.price)))
+    } else if let Some(listing) = listing {
+        // Route is up for auction, sent funds must cover the current ask price
+        let ask = listing.price_at(env.block.time.seconds());
+        let sent = find_coin(sent_funds, &ask.denom)?;
+
+        if sent.amount < ask.amount {
+            return Err(ContractError::InsufficientFunds {
+                sent: sent.amount,
+                required: ask.amount,
+            });
+        }
+
+        listings(storage).remove(route.as_bytes());
 
-        (sent, messages)
+        (sent, None)
     } else {
-        let mut sent = info.sent_funds;
         // No existing entry, continue with purchase
-        (sent.pop().ok_or(ContractError::InvalidCoins {})?, vec![])
+        (one_coin(sent_funds)?, None)
     };
 
-    resolver(deps.storage).save(
+    resolver(storage).save(
         route.as_bytes(),
         &ContentRecord {
             content,
-            price: new_price,
-            owner: info.sender,
+            price: new_price.clone(),
+            owner: sender.clone(),
+            harberger: None,
         },
     )?;
 
-    Ok(HandleResponse {
-        messages,
-        ..Default::default()
-    })
+    Ok((new_price, refund))
+}
+
+/// Merges a refund into the list of refunds, so each recipient is sent a single message.
+fn add_refund(refunds: &mut Vec<(Addr, Vec<Coin>)>, owner: Addr, price: Coin) {
+    let amount = match refunds.iter_mut().find(|(addr, _)| *addr == owner) {
+        Some((_, amount)) => amount,
+        None => {
+            refunds.push((owner, vec![]));
+            &mut refunds.last_mut().unwrap().1
+        }
+    };
+    match amount.iter_mut().find(|coin| coin.denom == price.denom) {
+        Some(coin) => coin.amount += price.amount,
+        None => amount.push(price),
+    }
 }
 
-fn try_withdraw(
+fn try_harberger_purchase(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-) -> Result<HandleResponse, ContractError> {
+    route: String,
+    content: String,
+    assessed_price: Uint128,
+) -> Result<Response, ContractError> {
     let state = config_read(deps.storage).load()?;
-    if deps.api.canonical_address(&info.sender)? != state.owner {
+    if state.paused {
+        return Err(ContractError::Paused {});
+    }
+
+    let rate = state.harberger_tax_rate;
+    let resolved = resolver_read(deps.storage).may_load(route.as_bytes())?;
+    let listing = listings_read(deps.storage).may_load(route.as_bytes())?;
+
+    // `paid` is the portion of sent funds paying for the route, the rest is prepaid tax.
+    let (sent, paid, mut messages) = if let Some(mut existing) = resolved {
+        if existing.harberger.is_none() {
+            return Err(ContractError::NotHarbergerRoute {});
+        }
+        let sent = find_coin(info.funds, &existing.price.denom)?;
+
+        let collected = existing.accrue_tax(rate, env.block.height);
+        let (paid, messages) = if existing.is_foreclosed() {
+            // Holder ran out of prepaid tax, route can be taken without paying them
+            (Uint128::zero(), vec![])
+        } else {
+            if sent.amount < existing.price.amount {
+                return Err(ContractError::InsufficientFunds {
+                    sent: sent.amount,
+                    required: existing.price.amount,
+                });
+            }
+
+            // Pay existing owner their assessed price and return unused prepaid tax.
+            let unused_tax = existing
+                .harberger
+                .map(|tax| tax.balance)
+                .unwrap_or_default();
+            let payment = Coin {
+                denom: sent.denom.clone(),
+                amount: existing.price.amount + unused_tax,
+            };
+            let messages = send_tokens(&existing.owner, vec![payment])?;
+
+            (existing.price.amount, messages)
+        };
+        credit_tax(deps.storage, &existing.price.denom, collected)?;
+
+        (sent, paid, messages)
+    } else if let Some(listing) = listing {
+        // Route is up for auction, sent funds must cover the current ask price
+        let ask = listing.price_at(env.block.time.seconds());
+        let sent = find_coin(info.funds, &ask.denom)?;
+
+        if sent.amount < ask.amount {
+            return Err(ContractError::InsufficientFunds {
+                sent: sent.amount,
+                required: ask.amount,
+            });
+        }
+
+        listings(deps.storage).remove(route.as_bytes());
+        // The ask isn't escrowed for anyone, so it's collected by the contract owner
+        credit_tax(deps.storage, &ask.denom, ask.amount)?;
+
+        (sent, ask.amount, vec![])
+    } else {
+        // No existing entry, all funds sent are prepaid tax
+        (one_coin(info.funds)?, Uint128::zero(), vec![])
+    };
+
+    let price = Coin {
+        denom: sent.denom,
+        amount: assessed_price,
+    };
+    resolver(deps.storage).save(
+        route.as_bytes(),
+        &ContentRecord {
+            content,
+            price: price.clone(),
+            owner: info.sender.clone(),
+            harberger: Some(HarbergerTax {
+                balance: sent.amount - paid,
+                last_accrued: env.block.height,
+            }),
+        },
+    )?;
+
+    messages.extend(notify_subscribers(deps.storage, route, info.sender, price)?);
+
+    Ok(Response::new().add_messages(messages))
+}
+
+fn try_harberger_top_up(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    route: String,
+) -> Result<Response, ContractError> {
+    let (mut record, collected) = load_harberger_holding(deps.storage, &env, &info, &route)?;
+    let sent = find_coin(info.funds, &record.price.denom)?;
+
+    if let Some(tax) = record.harberger.as_mut() {
+        tax.balance += sent.amount;
+    }
+    credit_tax(deps.storage, &record.price.denom, collected)?;
+    resolver(deps.storage).save(route.as_bytes(), &record)?;
+
+    Ok(Response::default())
+}
+
+fn try_harberger_reprice(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    route: String,
+    assessed_price: Uint128,
+) -> Result<Response, ContractError> {
+    let (mut record, collected) = load_harberger_holding(deps.storage, &env, &info, &route)?;
+
+    credit_tax(deps.storage, &record.price.denom, collected)?;
+    record.price.amount = assessed_price;
+    resolver(deps.storage).save(route.as_bytes(), &record)?;
+
+    Ok(Response::default())
+}
+
+/// Loads a Harberger route held by the sender, with tax accrued up to the current block.
+/// Returns the record along with the tax collected, which the caller credits to the owner.
+fn load_harberger_holding(
+    storage: &dyn Storage,
+    env: &Env,
+    info: &MessageInfo,
+    route: &str,
+) -> Result<(ContentRecord, Uint128), ContractError> {
+    let rate = config_read(storage).load()?.harberger_tax_rate;
+    let mut record = resolver_read(storage).load(route.as_bytes())?;
+    if record.harberger.is_none() {
+        return Err(ContractError::NotHarbergerRoute {});
+    }
+    if record.owner != info.sender {
         return Err(ContractError::Unauthorized {});
     }
 
-    let tokens = deps.querier.query_all_balances(&env.contract.address)?;
+    let collected = record.accrue_tax(rate, env.block.height);
+    if record.is_foreclosed() {
+        return Err(ContractError::Foreclosed {});
+    }
+
+    Ok((record, collected))
+}
 
-    let messages = send_tokens(&env.contract.address, &info.sender, tokens)?;
+/// Credits collected Harberger tax or auction proceeds to the owner ledger.
+fn credit_tax(storage: &mut dyn Storage, denom: &str, amount: Uint128) -> StdResult<()> {
+    if amount.is_zero() {
+        return Ok(());
+    }
 
-    Ok(HandleResponse {
-        messages,
-        ..Default::default()
-    })
+    let mut ledger = tax_ledger_read(storage).may_load()?.unwrap_or_default();
+    match ledger.iter_mut().find(|coin| coin.denom == denom) {
+        Some(coin) => coin.amount += amount,
+        None => ledger.push(Coin {
+            denom: denom.to_string(),
+            amount,
+        }),
+    }
+    tax_ledger(storage).save(&ledger)
+}
+
+fn try_set_tax_rate(
+    deps: DepsMut,
+    info: MessageInfo,
+    rate: Decimal,
+) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+
+    update_config(deps, None, Some(rate))
+}
+
+fn try_takedown(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    route: String,
+) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+
+    takedown_route(deps, env, route)
+}
+
+fn try_set_paused(
+    deps: DepsMut,
+    info: MessageInfo,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+
+    set_paused(deps, paused)
+}
+
+fn try_subscribe(
+    deps: DepsMut,
+    info: MessageInfo,
+    contract: String,
+) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+    let contract = deps.api.addr_validate(&contract)?;
+
+    let mut contracts = subscribers_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    if !contracts.contains(&contract) {
+        if contracts.len() >= MAX_SUBSCRIBERS {
+            return Err(ContractError::TooManySubscribers {
+                max: MAX_SUBSCRIBERS,
+            });
+        }
+        contracts.push(contract);
+        subscribers(deps.storage).save(&contracts)?;
+    }
+
+    Ok(Response::default())
+}
+
+fn try_unsubscribe(
+    deps: DepsMut,
+    info: MessageInfo,
+    contract: String,
+) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+    let contract = deps.api.addr_validate(&contract)?;
+
+    let mut contracts = subscribers_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    contracts.retain(|addr| *addr != contract);
+    subscribers(deps.storage).save(&contracts)?;
+
+    Ok(Response::default())
+}
+
+/// Builds messages notifying subscribed contracts that a route changed hands.
+fn notify_subscribers(
+    storage: &dyn Storage,
+    route: String,
+    owner: Addr,
+    price: Coin,
+) -> StdResult<Vec<CosmosMsg>> {
+    let contracts = subscribers_read(storage).may_load()?.unwrap_or_default();
+    if contracts.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let msg = to_binary(&RouteHookMsg::RouteChanged {
+        route,
+        owner,
+        price,
+    })?;
+    Ok(contracts
+        .into_iter()
+        .map(|contract_addr| {
+            WasmMsg::Execute {
+                contract_addr: contract_addr.into(),
+                msg: msg.clone(),
+                funds: vec![],
+            }
+            .into()
+        })
+        .collect())
+}
+
+/// Removes a route and refunds the holder their escrowed price, or unused prepaid tax for
+/// Harberger routes.
+fn takedown_route(deps: DepsMut, env: Env, route: String) -> Result<Response, ContractError> {
+    let rate = config_read(deps.storage).load()?.harberger_tax_rate;
+    let mut record = resolver_read(deps.storage).load(route.as_bytes())?;
+
+    let refund = if record.harberger.is_some() {
+        let collected = record.accrue_tax(rate, env.block.height);
+        credit_tax(deps.storage, &record.price.denom, collected)?;
+        record.harberger.map(|tax| tax.balance).unwrap_or_default()
+    } else {
+        record.price.amount
+    };
+    resolver(deps.storage).remove(route.as_bytes());
+
+    let messages = if refund.is_zero() {
+        vec![]
+    } else {
+        let refund = Coin {
+            denom: record.price.denom,
+            amount: refund,
+        };
+        send_tokens(&record.owner, vec![refund])?
+    };
+
+    Ok(Response::new().add_messages(messages))
+}
+
+fn update_config(
+    deps: DepsMut,
+    owner: Option<String>,
+    harberger_tax_rate: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    let mut state = config_read(deps.storage).load()?;
+    if let Some(owner) = owner {
+        state.owner = deps.api.addr_canonicalize(&owner)?;
+    }
+    if let Some(rate) = harberger_tax_rate {
+        if rate > Decimal::one() {
+            return Err(ContractError::InvalidTaxRate {});
+        }
+        state.harberger_tax_rate = rate;
+    }
+    config(deps.storage).save(&state)?;
+
+    Ok(Response::default())
+}
+
+fn set_paused(deps: DepsMut, paused: bool) -> Result<Response, ContractError> {
+    config(deps.storage).update(|mut state| -> StdResult<_> {
+        state.paused = paused;
+        Ok(state)
+    })?;
+
+    Ok(Response::default())
+}
+
+fn try_withdraw(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+
+    // Only collected tax and auction proceeds belong to the owner, the rest of the balance is
+    // escrowed prices and prepaid tax owed back to route holders
+    let tokens = tax_ledger_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    tax_ledger(deps.storage).save(&vec![])?;
+
+    let messages = send_tokens(&info.sender, tokens)?;
+
+    Ok(Response::new().add_messages(messages))
+}
+
+fn try_list_route(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    route: String,
+    start_price: Coin,
+    end_price: Coin,
+    duration: u64,
+) -> Result<Response, ContractError> {
+    ensure_owner(deps.as_ref(), &info.sender)?;
+
+    // Only routes which have not been purchased can be auctioned
+    if resolver_read(deps.storage)
+        .may_load(route.as_bytes())?
+        .is_some()
+    {
+        return Err(ContractError::RouteTaken {});
+    }
+
+    if duration == 0
+        || start_price.denom != end_price.denom
+        || start_price.amount < end_price.amount
+    {
+        return Err(ContractError::InvalidAuction {});
+    }
+
+    listings(deps.storage).save(
+        route.as_bytes(),
+        &Listing {
+            start_price,
+            end_price,
+            start_time: env.block.time.seconds(),
+            duration,
+        },
+    )?;
+
+    Ok(Response::default())
+}
+
+fn ensure_owner(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    let state = config_read(deps.storage).load()?;
+    if deps.api.addr_canonicalize(sender.as_str())? != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+/// Returns the coin sent, which must be the only one and in the given denom.
+fn find_coin(sent_funds: Vec<Coin>, denom: &str) -> Result<Coin, ContractError> {
+    let sent = one_coin(sent_funds)?;
+    if sent.denom != denom {
+        return Err(ContractError::InvalidCoins {});
+    }
+    Ok(sent)
+}
+
+/// Returns the single coin sent. Funds in more than one denom are rejected rather than
+/// keeping coins which aren't recorded anywhere.
+fn one_coin(sent_funds: Vec<Coin>) -> Result<Coin, ContractError> {
+    let mut sent = sent_funds;
+    match (sent.pop(), sent.is_empty()) {
+        (Some(coin), true) => Ok(coin),
+        _ => Err(ContractError::InvalidCoins {}),
+    }
 }
 
-fn send_tokens(from: &HumanAddr, to: &HumanAddr, amount: Vec<Coin>) -> StdResult<Vec<CosmosMsg>> {
+fn send_tokens(to: &Addr, amount: Vec<Coin>) -> StdResult<Vec<CosmosMsg>> {
     if amount.is_empty() {
         Ok(vec![])
     } else {
         let msg = BankMsg::Send {
-            from_address: from.into(),
             to_address: to.into(),
             amount,
         };
@@ -123,9 +692,14 @@ fn send_tokens(from: &HumanAddr, to: &HumanAddr, amount: Vec<Coin>) -> StdResult
     }
 }
 
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetRoute { route } => to_binary(&query_route(deps, route)?),
+        QueryMsg::CurrentAskPrice { route } => to_binary(&query_ask_price(deps, env, route)?),
+        QueryMsg::HarbergerInfo { route } => to_binary(&query_harberger(deps, env, route)?),
+        QueryMsg::TaxCollected {} => to_binary(&query_tax_collected(deps)?),
+        QueryMsg::Subscribers {} => to_binary(&query_subscribers(deps)?),
     }
 }
 
@@ -135,23 +709,65 @@ fn query_route(deps: Deps, route: String) -> StdResult<Option<ContentResponse>>
         .map(From::from))
 }
 
+fn query_ask_price(deps: Deps, env: Env, route: String) -> StdResult<Option<AskPriceResponse>> {
+    Ok(listings_read(deps.storage)
+        .may_load(route.as_bytes())?
+        .map(|listing| AskPriceResponse {
+            price: listing.price_at(env.block.time.seconds()),
+        }))
+}
+
+fn query_harberger(deps: Deps, env: Env, route: String) -> StdResult<Option<HarbergerResponse>> {
+    let rate = config_read(deps.storage).load()?.harberger_tax_rate;
+    let mut record = match resolver_read(deps.storage).may_load(route.as_bytes())? {
+        Some(record) if record.harberger.is_some() => record,
+        _ => return Ok(None),
+    };
+
+    record.accrue_tax(rate, env.block.height);
+    let foreclosed = record.is_foreclosed();
+    Ok(Some(HarbergerResponse {
+        tax_balance: record.harberger.map(|tax| tax.balance).unwrap_or_default(),
+        assessed_price: record.price,
+        foreclosed,
+    }))
+}
+
+fn query_tax_collected(deps: Deps) -> StdResult<TaxCollectedResponse> {
+    Ok(TaxCollectedResponse {
+        amount: tax_ledger_read(deps.storage)
+            .may_load()?
+            .unwrap_or_default(),
+    })
+}
+
+fn query_subscribers(deps: Deps) -> StdResult<SubscribersResponse> {
+    Ok(SubscribersResponse {
+        subscribers: subscribers_read(deps.storage)
+            .may_load()?
+            .unwrap_or_default(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cosmwasm_std::{coins, from_binary};
-    use cosmwasm_std::{
-        testing::{mock_dependencies, mock_env, mock_info},
-        Uint128,
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_dependencies_with_balance, mock_env, mock_info,
     };
+    use cosmwasm_std::{coin, coins, from_binary, SubMsg};
+    use proptest::{collection, option, prelude::*};
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
 
     #[test]
     fn proper_initialization() {
-        let mut deps = mock_dependencies(&[]);
+        let mut deps = mock_dependencies();
 
         let msg = InitMsg {};
         let info = mock_info("creator", &coins(1000, "earth"));
 
-        let res = init(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(0, res.messages.len());
 
         let res = query(
@@ -168,11 +784,11 @@ mod tests {
 
     #[test]
     fn purchase_and_replace() {
-        let mut deps = mock_dependencies(&coins(2, "token"));
+        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
 
         let msg = InitMsg {};
         let info = mock_info("creator", &coins(2, "token"));
-        let _res = init(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         // Simple route purchase works
         let info = mock_info("addr1", &coins(2, "token"));
@@ -180,7 +796,7 @@ mod tests {
             route: "troute".to_string(),
             content: "tcontent".to_string(),
         };
-        let _res = handle(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         let res = query(
             deps.as_ref(),
@@ -197,7 +813,7 @@ mod tests {
                 content: "tcontent".into(),
                 price: Coin {
                     denom: "token".into(),
-                    amount: Uint128(2)
+                    amount: Uint128::new(2)
                 }
             }
         );
@@ -208,7 +824,7 @@ mod tests {
             route: "troute".to_string(),
             content: "null".to_string(),
         };
-        let res = handle(deps.as_mut(), mock_env(), info, msg);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
         println!("{:?}", res);
         assert!(matches!(res, Err(ContractError::InvalidCoins {})));
 
@@ -218,7 +834,7 @@ mod tests {
             route: "troute".to_string(),
             content: "c2".to_string(),
         };
-        let _res = handle(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         let res = query(
             deps.as_ref(),
@@ -235,14 +851,1082 @@ mod tests {
                 content: "c2".into(),
                 price: Coin {
                     denom: "token".into(),
-                    amount: Uint128(4)
+                    amount: Uint128::new(4)
                 }
             }
         );
 
         let info = mock_info("creator", &[]);
         let msg = HandleMsg::Withdraw {};
-        let res = handle(deps.as_mut(), mock_env(), info, msg);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn dutch_auction_decay() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        // Only the contract owner can list routes
+        let msg = HandleMsg::ListRoute {
+            route: "troute".to_string(),
+            start_price: coin(100, "token"),
+            end_price: coin(10, "token"),
+            duration: 90,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr1", &[]),
+            msg.clone(),
+        );
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+
+        // Price can't increase over the auction
+        let bad_msg = HandleMsg::ListRoute {
+            route: "troute".to_string(),
+            start_price: coin(10, "token"),
+            end_price: coin(100, "token"),
+            duration: 90,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            bad_msg,
+        );
+        assert!(matches!(res, Err(ContractError::InvalidAuction {})));
+
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let query_ask = |deps: Deps, env: Env| -> Option<AskPriceResponse> {
+            let msg = QueryMsg::CurrentAskPrice {
+                route: "troute".to_string(),
+            };
+            from_binary(&query(deps, env, msg).unwrap()).unwrap()
+        };
+        assert_eq!(
+            query_ask(deps.as_ref(), mock_env()).unwrap().price,
+            coin(100, "token")
+        );
+
+        // Two thirds through the auction
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(60);
+        assert_eq!(
+            query_ask(deps.as_ref(), env.clone()).unwrap().price,
+            coin(40, "token")
+        );
+
+        // Sending less than the current ask fails
+        let purchase = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let info = mock_info("addr1", &coins(39, "token"));
+        let res = execute(deps.as_mut(), env.clone(), info, purchase.clone());
+        assert!(matches!(
+            res,
+            Err(ContractError::InsufficientFunds { sent, required })
+                if sent == Uint128::new(39) && required == Uint128::new(40)
+        ));
+
+        let info = mock_info("addr1", &coins(40, "token"));
+        let _res = execute(deps.as_mut(), env.clone(), info, purchase).unwrap();
+
+        // Listing is removed once the route is purchased
+        assert!(query_ask(deps.as_ref(), env.clone()).is_none());
+        let res = query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::GetRoute {
+                route: "troute".to_string(),
+            },
+        )
+        .unwrap();
+        let value: Option<ContentResponse> = from_binary(&res).unwrap();
+        assert_eq!(value.unwrap().price, coin(40, "token"));
+
+        // Purchased routes can't be listed again
+        let msg = HandleMsg::ListRoute {
+            route: "troute".to_string(),
+            start_price: coin(100, "token"),
+            end_price: coin(10, "token"),
+            duration: 90,
+        };
+        let res = execute(deps.as_mut(), env, mock_info("creator", &[]), msg);
+        assert!(matches!(res, Err(ContractError::RouteTaken {})));
+    }
+
+    #[test]
+    fn auction_price_floors_at_end_price() {
+        let listing = Listing {
+            start_price: coin(100, "token"),
+            end_price: coin(10, "token"),
+            start_time: 1_000,
+            duration: 90,
+        };
+        assert_eq!(listing.price_at(900), coin(100, "token"));
+        assert_eq!(listing.price_at(1_045), coin(55, "token"));
+        assert_eq!(listing.price_at(1_090), coin(10, "token"));
+        assert_eq!(listing.price_at(5_000), coin(10, "token"));
+    }
+
+    #[test]
+    fn harberger_purchase_of_listed_route() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = HandleMsg::ListRoute {
+            route: "troute".to_string(),
+            start_price: coin(100, "token"),
+            end_price: coin(10, "token"),
+            duration: 90,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // The ask is collected for the owner, the rest of the funds are prepaid tax
+        let msg = HandleMsg::HarbergerPurchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+            assessed_price: Uint128::new(200),
+        };
+        let info = mock_info("addr1", &coins(130, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert!(res.messages.is_empty());
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::TaxCollected {}).unwrap();
+        let value: TaxCollectedResponse = from_binary(&res).unwrap();
+        assert_eq!(value.amount, coins(100, "token"));
+        let msg = QueryMsg::HarbergerInfo {
+            route: "troute".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: Option<HarbergerResponse> = from_binary(&res).unwrap();
+        assert_eq!(value.unwrap().tax_balance, Uint128::new(30));
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            HandleMsg::Withdraw {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(100, "token"),
+            })]
+        );
+    }
+
+    #[test]
+    fn extra_denoms_rejected() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let purchase = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let harberger_purchase = HandleMsg::HarbergerPurchase {
+            route: "hroute".to_string(),
+            content: "tcontent".to_string(),
+            assessed_price: Uint128::new(10),
+        };
+        let two_denoms = [coin(2, "token"), coin(1, "other")];
+
+        // Unclaimed routes can't be bought with more than one denom
+        let info = mock_info("addr1", &two_denoms);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), purchase.clone());
+        assert!(matches!(res, Err(ContractError::InvalidCoins {})));
+        let res = execute(deps.as_mut(), mock_env(), info, harberger_purchase.clone());
+        assert!(matches!(res, Err(ContractError::InvalidCoins {})));
+
+        // Nor can taken routes, even when one of the coins covers the price
+        let info = mock_info("addr1", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), purchase.clone()).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), info, harberger_purchase.clone()).unwrap();
+        let info = mock_info("addr2", &[coin(5, "token"), coin(1, "other")]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), purchase);
+        assert!(matches!(res, Err(ContractError::InvalidCoins {})));
+        let res = execute(deps.as_mut(), mock_env(), info, harberger_purchase);
+        assert!(matches!(res, Err(ContractError::InvalidCoins {})));
+        let top_up = HandleMsg::HarbergerTopUp {
+            route: "hroute".to_string(),
+        };
+        let info = mock_info("addr1", &two_denoms);
+        let res = execute(deps.as_mut(), mock_env(), info, top_up);
+        assert!(matches!(res, Err(ContractError::InvalidCoins {})));
+    }
+
+    #[test]
+    fn harberger_tax() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = HandleMsg::SetTaxRate {
+            rate: Decimal::percent(1),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr1", &[]),
+            msg.clone(),
+        );
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let harberger_info = |deps: Deps, env: Env| -> HarbergerResponse {
+            let msg = QueryMsg::HarbergerInfo {
+                route: "troute".to_string(),
+            };
+            let value: Option<HarbergerResponse> =
+                from_binary(&query(deps, env, msg).unwrap()).unwrap();
+            value.unwrap()
+        };
+        let tax_collected = |deps: Deps| -> Vec<Coin> {
+            let res = query(deps, mock_env(), QueryMsg::TaxCollected {}).unwrap();
+            from_binary::<TaxCollectedResponse>(&res).unwrap().amount
+        };
+        let env_at = |blocks: u64| -> Env {
+            let mut env = mock_env();
+            env.block.height += blocks;
+            env
+        };
+
+        // Unclaimed route, all funds are prepaid tax
+        let msg = HandleMsg::HarbergerPurchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+            assessed_price: Uint128::new(100),
+        };
+        let info = mock_info("addr1", &coins(50, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert!(res.messages.is_empty());
+
+        // Harberger routes can't be outbid through a regular purchase
+        let msg = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "c2".to_string(),
+        };
+        let info = mock_info("addr2", &coins(1000, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        assert!(matches!(res, Err(ContractError::HarbergerRoute {})));
+
+        // 1% of 100 per block over 10 blocks
+        assert_eq!(
+            harberger_info(deps.as_ref(), env_at(10)),
+            HarbergerResponse {
+                assessed_price: coin(100, "token"),
+                tax_balance: Uint128::new(40),
+                foreclosed: false,
+            }
+        );
+
+        let msg = HandleMsg::HarbergerPurchase {
+            route: "troute".to_string(),
+            content: "c2".to_string(),
+            assessed_price: Uint128::new(150),
+        };
+        let info = mock_info("addr2", &coins(99, "token"));
+        let res = execute(deps.as_mut(), env_at(10), info, msg.clone());
+        assert!(matches!(
+            res,
+            Err(ContractError::InsufficientFunds { sent, required })
+                if sent == Uint128::new(99) && required == Uint128::new(100)
+        ));
+
+        // Only the holder can reprice or top up
+        let reprice = HandleMsg::HarbergerReprice {
+            route: "troute".to_string(),
+            assessed_price: Uint128::new(200),
+        };
+        let res = execute(
+            deps.as_mut(),
+            env_at(10),
+            mock_info("addr2", &[]),
+            reprice.clone(),
+        );
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+        let _res = execute(deps.as_mut(), env_at(10), mock_info("addr1", &[]), reprice).unwrap();
+        assert_eq!(tax_collected(deps.as_ref()), vec![coin(10, "token")]);
+
+        // Tax is charged on the new price, 20 over 10 blocks
+        let info = mock_info("addr2", &coins(250, "token"));
+        let res = execute(deps.as_mut(), env_at(20), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "addr1".into(),
+                amount: coins(220, "token"),
+            })]
+        );
+        assert_eq!(tax_collected(deps.as_ref()), vec![coin(30, "token")]);
+        assert_eq!(
+            harberger_info(deps.as_ref(), env_at(20)).tax_balance,
+            Uint128::new(50)
+        );
+
+        // Prepaid tax runs out after 34 blocks at 1.5 per block
+        let foreclosed = harberger_info(deps.as_ref(), env_at(54));
+        assert!(foreclosed.foreclosed);
+        let msg = HandleMsg::HarbergerTopUp {
+            route: "troute".to_string(),
+        };
+        let info = mock_info("addr2", &coins(100, "token"));
+        let res = execute(deps.as_mut(), env_at(54), info, msg);
+        assert!(matches!(res, Err(ContractError::Foreclosed {})));
+
+        // Foreclosed routes are taken without paying the previous holder
+        let msg = HandleMsg::HarbergerPurchase {
+            route: "troute".to_string(),
+            content: "c3".to_string(),
+            assessed_price: Uint128::new(10),
+        };
+        let info = mock_info("addr3", &coins(5, "token"));
+        let res = execute(deps.as_mut(), env_at(54), info, msg).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(tax_collected(deps.as_ref()), vec![coin(80, "token")]);
+
+        // Withdraw pays out only the collected tax, leaving prepaid tax in the contract
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            HandleMsg::Withdraw {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(80, "token"),
+            })]
+        );
+        assert!(tax_collected(deps.as_ref()).is_empty());
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            HandleMsg::Withdraw {},
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+    }
+
+    #[test]
+    fn harberger_huge_assessed_price() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        // Rates over 100% per block are rejected
+        let msg = HandleMsg::SetTaxRate {
+            rate: Decimal::percent(101),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg);
+        assert!(matches!(res, Err(ContractError::InvalidTaxRate {})));
+        let msg = HandleMsg::SetTaxRate {
+            rate: Decimal::percent(1),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = HandleMsg::HarbergerPurchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+            assessed_price: Uint128::new(100),
+        };
+        let info = mock_info("addr1", &coins(50, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = HandleMsg::HarbergerReprice {
+            route: "troute".to_string(),
+            assessed_price: Uint128::new(u128::MAX),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("addr1", &[]), msg).unwrap();
+
+        // Tax owed on the huge price exhausts the prepaid balance instead of overflowing
+        let mut env = mock_env();
+        env.block.height += 1;
+        let msg = QueryMsg::HarbergerInfo {
+            route: "troute".to_string(),
+        };
+        let res = query(deps.as_ref(), env.clone(), msg).unwrap();
+        let value: Option<HarbergerResponse> = from_binary(&res).unwrap();
+        assert!(value.unwrap().foreclosed);
+
+        let msg = HandleMsg::Takedown {
+            route: "troute".to_string(),
+        };
+        let res = execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+        assert!(res.messages.is_empty());
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::TaxCollected {}).unwrap();
+        let value: TaxCollectedResponse = from_binary(&res).unwrap();
+        assert_eq!(value.amount, coins(50, "token"));
+    }
+
+    #[test]
+    fn tax_owed_matches_exact_arithmetic() {
+        let mut record = ContentRecord {
+            price: coin(150, "token"),
+            content: "tcontent".to_string(),
+            owner: Addr::unchecked("addr1"),
+            harberger: Some(HarbergerTax {
+                balance: Uint128::new(u128::MAX),
+                last_accrued: 0,
+            }),
+        };
+        // 1.5 per block, rounded down over the whole period rather than per block
+        assert_eq!(record.accrue_tax(Decimal::percent(1), 3), Uint128::new(4));
+
+        record.price.amount = Uint128::new(u128::MAX);
+        assert_eq!(
+            record.accrue_tax(Decimal::permille(1), 7),
+            Uint128::new(u128::MAX / 1000 * 4 + 1)
+        );
+        assert_eq!(
+            record.accrue_tax(Decimal::one(), u64::MAX),
+            Uint128::new(u128::MAX - 4 - (u128::MAX / 1000 * 4 + 1))
+        );
+    }
+
+    #[test]
+    fn owner_takedown() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let info = mock_info("addr1", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // Only the owner can take down routes
+        let msg = HandleMsg::Takedown {
+            route: "troute".to_string(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr2", &[]),
+            msg.clone(),
+        );
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "addr1".into(),
+                amount: coins(2, "token"),
+            })]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::GetRoute {
+                route: "troute".to_string(),
+            },
+        )
+        .unwrap();
+        let value: Option<ContentResponse> = from_binary(&res).unwrap();
+        assert!(value.is_none());
+
+        // Route no longer exists
+        assert!(execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).is_err());
+    }
+
+    #[test]
+    fn pause() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let purchase = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let harberger_purchase = HandleMsg::HarbergerPurchase {
+            route: "hroute".to_string(),
+            content: "tcontent".to_string(),
+            assessed_price: Uint128::new(10),
+        };
+
+        // Only the owner can pause
+        let info = mock_info("addr1", &coins(2, "token"));
+        let msg = HandleMsg::SetPaused { paused: true };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone());
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), purchase.clone());
+        assert!(matches!(res, Err(ContractError::Paused {})));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), harberger_purchase);
+        assert!(matches!(res, Err(ContractError::Paused {})));
+
+        let msg = HandleMsg::SetPaused { paused: false };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), info, purchase).unwrap();
+    }
+
+    #[test]
+    fn sudo_force_takedown() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let info = mock_info("addr1", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // Governance takes down routes without the owner key
+        let msg = SudoMsg::ForceTakedown {
+            route: "troute".to_string(),
+        };
+        let res = sudo(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "addr1".into(),
+                amount: coins(2, "token"),
+            })]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::GetRoute {
+                route: "troute".to_string(),
+            },
+        )
+        .unwrap();
+        let value: Option<ContentResponse> = from_binary(&res).unwrap();
+        assert!(value.is_none());
+
+        // Route no longer exists
+        let msg = SudoMsg::ForceTakedown {
+            route: "troute".to_string(),
+        };
+        assert!(sudo(deps.as_mut(), mock_env(), msg).is_err());
+    }
+
+    #[test]
+    fn sudo_set_config() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = SudoMsg::SetConfig {
+            owner: Some("governance".to_string()),
+            harberger_tax_rate: Some(Decimal::permille(5)),
+        };
+        let _res = sudo(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.harberger_tax_rate, Decimal::permille(5));
+
+        // Ownership moved to the new address
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, HandleMsg::Withdraw {});
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+        let info = mock_info("governance", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, HandleMsg::Withdraw {}).unwrap();
+
+        // Rates are bounded as for the owner
+        let msg = SudoMsg::SetConfig {
+            owner: None,
+            harberger_tax_rate: Some(Decimal::percent(101)),
+        };
+        let res = sudo(deps.as_mut(), mock_env(), msg);
+        assert!(matches!(res, Err(ContractError::InvalidTaxRate {})));
+
+        // Unset fields are left unchanged
+        let msg = SudoMsg::SetConfig {
+            owner: None,
+            harberger_tax_rate: None,
+        };
+        let _res = sudo(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(config_read(&deps.storage).load().unwrap(), state);
+    }
+
+    #[test]
+    fn sudo_pause() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let purchase = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+
+        let _res = sudo(deps.as_mut(), mock_env(), SudoMsg::Pause { paused: true }).unwrap();
+        let info = mock_info("addr1", &coins(2, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), purchase.clone());
+        assert!(matches!(res, Err(ContractError::Paused {})));
+
+        let _res = sudo(deps.as_mut(), mock_env(), SudoMsg::Pause { paused: false }).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), info, purchase).unwrap();
+    }
+
+    #[test]
+    fn purchase_batch() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        for (sender, route, funds) in [
+            ("addr1", "route1", coin(2, "token")),
+            ("addr1", "route2", coin(3, "token")),
+            ("addr2", "route3", coin(1, "other")),
+        ]
+        .iter()
+        {
+            let msg = HandleMsg::Purchase {
+                route: route.to_string(),
+                content: "tcontent".to_string(),
+            };
+            let info = mock_info(sender, std::slice::from_ref(funds));
+            let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        let item = |route: &str, amount: Coin| PurchaseItem {
+            route: route.to_string(),
+            content: "batch".to_string(),
+            amount,
+        };
+        let msg = HandleMsg::PurchaseBatch {
+            items: vec![
+                item("route1", coin(5, "token")),
+                item("route2", coin(4, "token")),
+                item("route3", coin(2, "other")),
+                item("route4", coin(1, "token")),
+            ],
+        };
+
+        // Sent funds must cover the sum of bids
+        let info = mock_info("addr3", &[coin(9, "token"), coin(2, "other")]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
+        assert!(matches!(
+            res,
+            Err(ContractError::InsufficientFunds { sent, required })
+                if sent == Uint128::new(9) && required == Uint128::new(10)
+        ));
+
+        // Refunds are merged per recipient, excess funds are returned to the sender
+        let info = mock_info("addr3", &[coin(12, "token"), coin(2, "other")]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let send = |to: &str, amount: Vec<Coin>| -> SubMsg {
+            SubMsg::new(BankMsg::Send {
+                to_address: to.into(),
+                amount,
+            })
+        };
+        assert_eq!(
+            res.messages,
+            vec![
+                send("addr1", coins(5, "token")),
+                send("addr2", coins(1, "other")),
+                send("addr3", coins(2, "token")),
+            ]
+        );
+
+        for (route, price) in [
+            ("route1", coin(5, "token")),
+            ("route2", coin(4, "token")),
+            ("route3", coin(2, "other")),
+            ("route4", coin(1, "token")),
+        ]
+        .iter()
+        {
+            let msg = QueryMsg::GetRoute {
+                route: route.to_string(),
+            };
+            let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+            let value: Option<ContentResponse> = from_binary(&res).unwrap();
+            assert_eq!(
+                value.unwrap(),
+                ContentResponse {
+                    content: "batch".into(),
+                    price: price.clone(),
+                }
+            );
+        }
+
+        // Any underbid item fails the whole batch
+        let msg = HandleMsg::PurchaseBatch {
+            items: vec![
+                item("route5", coin(1, "token")),
+                item("route1", coin(5, "token")),
+            ],
+        };
+        let info = mock_info("addr1", &coins(6, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        assert!(matches!(res, Err(ContractError::InsufficientFunds { .. })));
+    }
+
+    #[test]
+    fn route_changed_hooks() {
+        // Handle message of a contract mirroring routes, standing in for a subscriber
+        #[derive(Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "snake_case")]
+        enum RegistryHandleMsg {
+            RouteChanged {
+                route: String,
+                owner: Addr,
+                price: Coin,
+            },
+        }
+
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        // Only the owner manages subscriptions
+        let msg = HandleMsg::Subscribe {
+            contract: "registry".into(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr1", &[]),
+            msg.clone(),
+        );
+        assert!(matches!(res, Err(ContractError::Unauthorized {})));
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let info = mock_info("addr1", &coins(2, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                funds,
+            }) => {
+                assert_eq!(contract_addr, "registry");
+                assert!(funds.is_empty());
+                assert_eq!(
+                    from_binary::<RegistryHandleMsg>(msg).unwrap(),
+                    RegistryHandleMsg::RouteChanged {
+                        route: "troute".to_string(),
+                        owner: Addr::unchecked("addr1"),
+                        price: coin(2, "token"),
+                    }
+                );
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+
+        // Subscriber count is capped
+        for i in 1..MAX_SUBSCRIBERS {
+            let msg = HandleMsg::Subscribe {
+                contract: format!("registry{}", i),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        }
+        let msg = HandleMsg::Subscribe {
+            contract: "one_too_many".into(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg);
+        assert!(matches!(res, Err(ContractError::TooManySubscribers { .. })));
+
+        // Outbid refund is sent first, followed by a notification to each subscriber
+        let msg = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "c2".to_string(),
+        };
+        let info = mock_info("addr2", &coins(3, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1 + MAX_SUBSCRIBERS);
+        assert!(matches!(res.messages[0].msg, CosmosMsg::Bank(_)));
+
+        let msg = HandleMsg::Unsubscribe {
+            contract: "registry".into(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Subscribers {}).unwrap();
+        let value: SubscribersResponse = from_binary(&res).unwrap();
+        assert_eq!(value.subscribers.len(), MAX_SUBSCRIBERS - 1);
+        assert!(!value.subscribers.contains(&Addr::unchecked("registry")));
+    }
+
+    #[test]
+    fn failing_subscriber_blocks_purchases() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = HandleMsg::Subscribe {
+            contract: "broken".into(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // The hook is a plain message in the purchase response rather than a submessage, so if
+        // the subscriber fails the chain reverts the purchase along with it
+        let purchase = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let info = mock_info("addr1", &coins(2, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, purchase.clone()).unwrap();
+        assert!(matches!(
+            &res.messages[..],
+            [SubMsg { msg: CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }), .. }]
+                if contract_addr == "broken"
+        ));
+
+        // Unsubscribing the failing contract lets purchases through again
+        let msg = HandleMsg::Unsubscribe {
+            contract: "broken".into(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let info = mock_info("addr2", &coins(3, "token"));
+        let res = execute(deps.as_mut(), mock_env(), info, purchase).unwrap();
+        assert!(matches!(
+            &res.messages[..],
+            [SubMsg {
+                msg: CosmosMsg::Bank(_),
+                ..
+            }]
+        ));
+    }
+
+    const SENDERS: [&str; 3] = ["creator", "addr1", "addr2"];
+    const ROUTES: [&str; 3] = ["route1", "route2", "route3"];
+
+    #[derive(Clone, Debug)]
+    enum Action {
+        Purchase {
+            sender: usize,
+            route: usize,
+            funds: Vec<Coin>,
+        },
+        Withdraw {
+            sender: usize,
+        },
+    }
+
+    fn funds_strategy() -> impl Strategy<Value = Vec<Coin>> {
+        (option::of(0u128..10), option::of(0u128..10)).prop_map(|(token, other)| {
+            token
+                .map(|amount| coin(amount, "token"))
+                .into_iter()
+                .chain(other.map(|amount| coin(amount, "other")))
+                .collect()
+        })
+    }
+
+    fn action_strategy() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            4 => (0..SENDERS.len(), 0..ROUTES.len(), funds_strategy())
+                .prop_map(|(sender, route, funds)| Action::Purchase { sender, route, funds }),
+            1 => (0..SENDERS.len()).prop_map(|sender| Action::Withdraw { sender }),
+        ]
+    }
+
+    fn add_coin(totals: &mut BTreeMap<String, u128>, coin: &Coin) {
+        *totals.entry(coin.denom.clone()).or_default() += coin.amount.u128();
+    }
+
+    /// Adds the funds sent with a successful message to `received` and the coins it sends back
+    /// out, as refunds or withdrawals, to `sent_out`.
+    fn record_transfers(
+        res: &Result<Response, ContractError>,
+        funds: &[Coin],
+        received: &mut BTreeMap<String, u128>,
+        sent_out: &mut BTreeMap<String, u128>,
+    ) {
+        if let Ok(res) = res {
+            for coin in funds {
+                add_coin(received, coin);
+            }
+            for msg in &res.messages {
+                if let CosmosMsg::Bank(BankMsg::Send { amount, .. }) = &msg.msg {
+                    for coin in amount {
+                        add_coin(sent_out, coin);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs a sequence of actions, checking results and escrow invariants after each one.
+    fn check_actions(actions: Vec<Action>) -> Result<(), TestCaseError> {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        // Expected holder and price paid for each route
+        let mut holders: BTreeMap<usize, (Addr, Coin)> = BTreeMap::new();
+        // Everything sent to the contract with successful messages, by denom
+        let mut received: BTreeMap<String, u128> = BTreeMap::new();
+        // Everything the contract sent back out as refunds or withdrawals, by denom
+        let mut sent_out: BTreeMap<String, u128> = BTreeMap::new();
+
+        for action in actions {
+            match action {
+                Action::Purchase {
+                    sender,
+                    route,
+                    funds,
+                } => {
+                    let info = mock_info(SENDERS[sender], &funds);
+                    let msg = HandleMsg::Purchase {
+                        route: ROUTES[route].to_string(),
+                        content: "content".to_string(),
+                    };
+                    let res = execute(deps.as_mut(), mock_env(), info, msg);
+                    record_transfers(&res, &funds, &mut received, &mut sent_out);
+
+                    match holders.get(&route) {
+                        Some((owner, price)) => {
+                            // Only a single coin in the denom of the price is accepted
+                            let sent = match &funds[..] {
+                                [sent] if sent.denom == price.denom => Some(sent),
+                                _ => None,
+                            };
+                            match sent {
+                                None => {
+                                    prop_assert!(
+                                        matches!(res, Err(ContractError::InvalidCoins {})),
+                                        "{:?}",
+                                        res
+                                    );
+                                }
+                                Some(sent) if sent.amount <= price.amount => {
+                                    prop_assert!(
+                                        matches!(
+                                            res,
+                                            Err(ContractError::InsufficientFunds { sent: s, required })
+                                                if s == sent.amount && required == price.amount
+                                        ),
+                                        "{:?}",
+                                        res
+                                    );
+                                }
+                                Some(sent) => {
+                                    // Previous holder is refunded exactly what they paid
+                                    let res = res.unwrap();
+                                    prop_assert_eq!(
+                                        res.messages,
+                                        vec![SubMsg::new(BankMsg::Send {
+                                            to_address: owner.to_string(),
+                                            amount: vec![price.clone()],
+                                        })]
+                                    );
+                                    holders.insert(
+                                        route,
+                                        (Addr::unchecked(SENDERS[sender]), sent.clone()),
+                                    );
+                                }
+                            }
+                        }
+                        None => match &funds[..] {
+                            [sent] => {
+                                prop_assert!(res.unwrap().messages.is_empty());
+                                holders.insert(
+                                    route,
+                                    (Addr::unchecked(SENDERS[sender]), sent.clone()),
+                                );
+                            }
+                            _ => {
+                                prop_assert!(
+                                    matches!(res, Err(ContractError::InvalidCoins {})),
+                                    "{:?}",
+                                    res
+                                );
+                            }
+                        },
+                    }
+                }
+                Action::Withdraw { sender } => {
+                    let info = mock_info(SENDERS[sender], &[]);
+                    let res = execute(deps.as_mut(), mock_env(), info, HandleMsg::Withdraw {});
+                    record_transfers(&res, &[], &mut received, &mut sent_out);
+                    if SENDERS[sender] == "creator" {
+                        prop_assert!(res.is_ok());
+                    } else {
+                        prop_assert!(
+                            matches!(res, Err(ContractError::Unauthorized {})),
+                            "{:?}",
+                            res
+                        );
+                    }
+                }
+            }
+
+            // Funds received and not sent back out are all accounted for, either escrowed for a
+            // live route or collected for the contract owner
+            let mut held = received.clone();
+            for (denom, amount) in &sent_out {
+                let total = held.entry(denom.clone()).or_default();
+                prop_assert!(*total >= *amount, "sent out more {} than received", denom);
+                *total -= amount;
+            }
+            let mut accounted = BTreeMap::new();
+            for route in ROUTES.iter() {
+                let msg = QueryMsg::GetRoute {
+                    route: route.to_string(),
+                };
+                let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+                let value: Option<ContentResponse> = from_binary(&res).unwrap();
+                if let Some(value) = value {
+                    add_coin(&mut accounted, &value.price);
+                }
+            }
+            let res = query(deps.as_ref(), mock_env(), QueryMsg::TaxCollected {}).unwrap();
+            let collected: TaxCollectedResponse = from_binary(&res).unwrap();
+            for coin in &collected.amount {
+                add_coin(&mut accounted, coin);
+            }
+            held.retain(|_, amount| *amount != 0);
+            accounted.retain(|_, amount| *amount != 0);
+            prop_assert_eq!(&held, &accounted);
+        }
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn purchase_refund_invariants(actions in collection::vec(action_strategy(), 1..50)) {
+            check_actions(actions)?;
+        }
+    }
 }
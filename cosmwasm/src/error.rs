@@ -14,4 +14,28 @@ pub enum ContractError {
 
     #[error("Invalid coins sent for purchase")]
     InvalidCoins {},
+
+    #[error("Route has already been purchased")]
+    RouteTaken {},
+
+    #[error("Invalid auction parameters")]
+    InvalidAuction {},
+
+    #[error("Route is held under Harberger tax")]
+    HarbergerRoute {},
+
+    #[error("Route is not held under Harberger tax")]
+    NotHarbergerRoute {},
+
+    #[error("Tax rate can't be more than 100% per block")]
+    InvalidTaxRate {},
+
+    #[error("Route has been foreclosed for unpaid tax")]
+    Foreclosed {},
+
+    #[error("Contract is paused")]
+    Paused {},
+
+    #[error("Cannot have more than {max:} subscribers")]
+    TooManySubscribers { max: usize },
 }
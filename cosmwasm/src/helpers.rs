@@ -0,0 +1,244 @@
+use cosmwasm_std::{
+    to_binary, Addr, Coin, CosmosMsg, QuerierWrapper, StdResult, Uint128, WasmMsg, WasmQuery,
+};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::msg::{
+    AskPriceResponse, ContentResponse, HandleMsg, HarbergerResponse, PurchaseItem, QueryMsg,
+    SubscribersResponse, TaxCollectedResponse,
+};
+
+/// Typed wrapper around the address of a deployed content tracker, used by other contracts to
+/// build messages to and query the contract.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContentTrackerContract(pub Addr);
+
+impl ContentTrackerContract {
+    pub fn addr(&self) -> Addr {
+        self.0.clone()
+    }
+
+    /// Builds a message executing `msg` on the contract, sending `funds` along with it.
+    pub fn call(&self, msg: HandleMsg, funds: Vec<Coin>) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr: self.addr().into(),
+            msg: to_binary(&msg)?,
+            funds,
+        }
+        .into())
+    }
+
+    /// Builds a message purchasing a route with the given funds.
+    pub fn purchase(
+        &self,
+        route: impl Into<String>,
+        content: impl Into<String>,
+        funds: Vec<Coin>,
+    ) -> StdResult<CosmosMsg> {
+        let msg = HandleMsg::Purchase {
+            route: route.into(),
+            content: content.into(),
+        };
+        self.call(msg, funds)
+    }
+
+    /// Builds a message purchasing multiple routes, sending the sum of all bids.
+    pub fn purchase_batch(&self, items: Vec<PurchaseItem>) -> StdResult<CosmosMsg> {
+        let mut funds: Vec<Coin> = Vec::new();
+        for item in items.iter() {
+            match funds
+                .iter_mut()
+                .find(|coin| coin.denom == item.amount.denom)
+            {
+                Some(coin) => coin.amount += item.amount.amount,
+                None => funds.push(item.amount.clone()),
+            }
+        }
+        self.call(HandleMsg::PurchaseBatch { items }, funds)
+    }
+
+    /// Builds a message purchasing a route under Harberger tax with the given funds.
+    pub fn harberger_purchase(
+        &self,
+        route: impl Into<String>,
+        content: impl Into<String>,
+        assessed_price: Uint128,
+        funds: Vec<Coin>,
+    ) -> StdResult<CosmosMsg> {
+        let msg = HandleMsg::HarbergerPurchase {
+            route: route.into(),
+            content: content.into(),
+            assessed_price,
+        };
+        self.call(msg, funds)
+    }
+
+    fn query<T: DeserializeOwned>(&self, querier: &QuerierWrapper, msg: &QueryMsg) -> StdResult<T> {
+        let request = WasmQuery::Smart {
+            contract_addr: self.addr().into(),
+            msg: to_binary(msg)?,
+        };
+        querier.query(&request.into())
+    }
+
+    /// Queries the content and price at a route.
+    pub fn get_route(
+        &self,
+        querier: &QuerierWrapper,
+        route: impl Into<String>,
+    ) -> StdResult<Option<ContentResponse>> {
+        let msg = QueryMsg::GetRoute {
+            route: route.into(),
+        };
+        self.query(querier, &msg)
+    }
+
+    /// Queries the current Dutch auction ask price of a listed route.
+    pub fn current_ask_price(
+        &self,
+        querier: &QuerierWrapper,
+        route: impl Into<String>,
+    ) -> StdResult<Option<AskPriceResponse>> {
+        let msg = QueryMsg::CurrentAskPrice {
+            route: route.into(),
+        };
+        self.query(querier, &msg)
+    }
+
+    /// Queries the self-assessed price and remaining tax balance of a Harberger route.
+    pub fn harberger_info(
+        &self,
+        querier: &QuerierWrapper,
+        route: impl Into<String>,
+    ) -> StdResult<Option<HarbergerResponse>> {
+        let msg = QueryMsg::HarbergerInfo {
+            route: route.into(),
+        };
+        self.query(querier, &msg)
+    }
+
+    /// Queries the Harberger tax and auction proceeds collected for the contract owner.
+    pub fn tax_collected(&self, querier: &QuerierWrapper) -> StdResult<TaxCollectedResponse> {
+        self.query(querier, &QueryMsg::TaxCollected {})
+    }
+
+    /// Queries the contracts notified when routes change hands.
+    pub fn subscribers(&self, querier: &QuerierWrapper) -> StdResult<SubscribersResponse> {
+        self.query(querier, &QueryMsg::Subscribers {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, query};
+    use crate::msg::InitMsg;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        coin, coins, from_binary, from_slice, ContractResult, Empty, OwnedDeps, Querier,
+        QuerierResult, QueryRequest, SystemError, SystemResult,
+    };
+
+    /// Answers smart queries by running them against the contract's state.
+    struct ContractQuerier {
+        deps: OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    }
+
+    impl Querier for ContractQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            let msg = match request {
+                QueryRequest::Wasm(WasmQuery::Smart { msg, .. }) => from_binary(&msg).unwrap(),
+                _ => {
+                    return SystemResult::Err(SystemError::UnsupportedRequest {
+                        kind: "non smart query".to_string(),
+                    })
+                }
+            };
+            SystemResult::Ok(ContractResult::from(query(
+                self.deps.as_ref(),
+                mock_env(),
+                msg,
+            )))
+        }
+    }
+
+    #[test]
+    fn purchase_msg() {
+        let contract = ContentTrackerContract(Addr::unchecked("tracker"));
+        let msg = contract
+            .purchase("troute", "tcontent", coins(2, "token"))
+            .unwrap();
+        assert_eq!(
+            msg,
+            WasmMsg::Execute {
+                contract_addr: "tracker".into(),
+                msg: to_binary(&HandleMsg::Purchase {
+                    route: "troute".to_string(),
+                    content: "tcontent".to_string(),
+                })
+                .unwrap(),
+                funds: coins(2, "token"),
+            }
+            .into()
+        );
+
+        // Batch purchases send the sum of bids
+        let item = |route: &str, amount: Coin| PurchaseItem {
+            route: route.to_string(),
+            content: "tcontent".to_string(),
+            amount,
+        };
+        let items = vec![
+            item("route1", coin(2, "token")),
+            item("route2", coin(1, "other")),
+            item("route3", coin(3, "token")),
+        ];
+        let msg = contract.purchase_batch(items.clone()).unwrap();
+        assert_eq!(
+            msg,
+            WasmMsg::Execute {
+                contract_addr: "tracker".into(),
+                msg: to_binary(&HandleMsg::PurchaseBatch { items }).unwrap(),
+                funds: vec![coin(5, "token"), coin(1, "other")],
+            }
+            .into()
+        );
+    }
+
+    #[test]
+    fn query_route() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InitMsg {}).unwrap();
+
+        let msg = HandleMsg::Purchase {
+            route: "troute".to_string(),
+            content: "tcontent".to_string(),
+        };
+        let info = mock_info("addr1", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let querier = ContractQuerier { deps };
+        let wrapper = QuerierWrapper::new(&querier);
+        let contract = ContentTrackerContract(Addr::unchecked("tracker"));
+
+        assert_eq!(
+            contract.get_route(&wrapper, "troute").unwrap(),
+            Some(ContentResponse {
+                content: "tcontent".into(),
+                price: coin(2, "token"),
+            })
+        );
+        assert_eq!(contract.get_route(&wrapper, "other").unwrap(), None);
+        assert_eq!(
+            contract.current_ask_price(&wrapper, "troute").unwrap(),
+            None
+        );
+        assert!(contract.tax_collected(&wrapper).unwrap().amount.is_empty());
+    }
+}
@@ -1,7 +1,5 @@
 pub mod contract;
 pub mod error;
+pub mod helpers;
 pub mod msg;
 pub mod state;
-
-#[cfg(target_arch = "wasm32")]
-cosmwasm_std::create_entry_points!(contract);
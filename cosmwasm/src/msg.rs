@@ -1,5 +1,5 @@
 use crate::state::ContentRecord;
-use cosmwasm_std::Coin;
+use cosmwasm_std::{Addr, Coin, Decimal, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -13,8 +13,83 @@ pub struct InitMsg {}
 pub enum HandleMsg {
     /// User sends token to purchase a route.
     Purchase { route: String, content: String },
-    /// Allows contract owner to withdraw funds.
+    /// User sends token to purchase multiple routes, bidding the given amount on each.
+    PurchaseBatch { items: Vec<PurchaseItem> },
+    /// Allows contract owner to withdraw collected Harberger tax and auction proceeds.
     Withdraw {},
+    /// Allows contract owner to list an unclaimed route as a Dutch auction, where the price
+    /// decays linearly from `start_price` to `end_price` over `duration` seconds.
+    ListRoute {
+        route: String,
+        start_price: Coin,
+        end_price: Coin,
+        duration: u64,
+    },
+    /// User sends token to purchase a route held under Harberger tax at its self-assessed price,
+    /// or at the current ask of a listed route, which is collected by the contract owner. Any
+    /// funds sent over the price are kept as prepaid tax.
+    HarbergerPurchase {
+        route: String,
+        content: String,
+        assessed_price: Uint128,
+    },
+    /// Route holder sends token to top up the prepaid tax balance.
+    HarbergerTopUp { route: String },
+    /// Route holder updates the self-assessed price of the route.
+    HarbergerReprice {
+        route: String,
+        assessed_price: Uint128,
+    },
+    /// Allows contract owner to set the per block Harberger tax rate, at most 100%.
+    SetTaxRate { rate: Decimal },
+    /// Allows contract owner to remove a route, refunding the holder.
+    Takedown { route: String },
+    /// Allows contract owner to pause or resume purchases.
+    SetPaused { paused: bool },
+    /// Allows contract owner to register a contract to be notified when routes change hands.
+    ///
+    /// Notifications are sent as regular messages, which execute atomically with the purchase.
+    /// If the subscriber errors or does not handle [RouteHookMsg::RouteChanged], every purchase
+    /// reverts until it is unsubscribed.
+    Subscribe { contract: String },
+    /// Allows contract owner to stop notifying a contract.
+    Unsubscribe { contract: String },
+}
+
+/// Message sent to subscribed contracts. Subscribers include this variant in their own handle
+/// message to receive it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteHookMsg {
+    /// A route was purchased by a new owner.
+    RouteChanged {
+        route: String,
+        owner: Addr,
+        price: Coin,
+    },
+}
+
+/// Single route bid within [HandleMsg::PurchaseBatch].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PurchaseItem {
+    pub route: String,
+    pub content: String,
+    pub amount: Coin,
+}
+
+/// Privileged messages sent by the chain, such as from governance proposals.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    /// Removes a route, refunding the holder.
+    ForceTakedown { route: String },
+    /// Updates the contract config, leaving fields which are not set unchanged.
+    SetConfig {
+        owner: Option<String>,
+        harberger_tax_rate: Option<Decimal>,
+    },
+    /// Pauses or resumes purchases.
+    Pause { paused: bool },
 }
 
 /// Queries defined for state.
@@ -23,6 +98,14 @@ pub enum HandleMsg {
 pub enum QueryMsg {
     /// Queries route and returns content.
     GetRoute { route: String },
+    /// Queries the current Dutch auction ask price of a listed route.
+    CurrentAskPrice { route: String },
+    /// Queries the self-assessed price and remaining tax balance of a Harberger route.
+    HarbergerInfo { route: String },
+    /// Queries the Harberger tax and auction proceeds collected for the contract owner.
+    TaxCollected {},
+    /// Queries the contracts notified when routes change hands.
+    Subscribers {},
 }
 
 /// Response type for [QueryMsg::GetRoute].
@@ -40,3 +123,29 @@ impl From<ContentRecord> for ContentResponse {
         }
     }
 }
+
+/// Response type for [QueryMsg::CurrentAskPrice].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AskPriceResponse {
+    pub price: Coin,
+}
+
+/// Response type for [QueryMsg::HarbergerInfo].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HarbergerResponse {
+    pub assessed_price: Coin,
+    pub tax_balance: Uint128,
+    pub foreclosed: bool,
+}
+
+/// Response type for [QueryMsg::TaxCollected].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TaxCollectedResponse {
+    pub amount: Vec<Coin>,
+}
+
+/// Response type for [QueryMsg::Subscribers].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SubscribersResponse {
+    pub subscribers: Vec<Addr>,
+}
@@ -1,7 +1,7 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Coin, HumanAddr, Storage};
+use cosmwasm_std::{Addr, CanonicalAddr, Coin, Decimal, Storage, Uint128};
 use cosmwasm_storage::{
     bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
     Singleton,
@@ -9,18 +9,30 @@ use cosmwasm_storage::{
 
 pub static CONFIG_KEY: &[u8] = b"config";
 pub static ROUTE_KEY: &[u8] = b"routes";
+pub static LISTING_KEY: &[u8] = b"listings";
+pub static TAX_KEY: &[u8] = b"tax";
+pub static SUBSCRIBERS_KEY: &[u8] = b"subscribers";
+
+/// Maximum number of contracts notified when a route changes hands.
+pub const MAX_SUBSCRIBERS: usize = 10;
 
 /// Stores config for the contract.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub owner: CanonicalAddr,
+    /// Tax charged per block on the self-assessed price of Harberger routes.
+    #[serde(default)]
+    pub harberger_tax_rate: Decimal,
+    /// Purchases are rejected while paused.
+    #[serde(default)]
+    pub paused: bool,
 }
 
-pub(crate) fn config(storage: &mut dyn Storage) -> Singleton<Config> {
+pub(crate) fn config(storage: &mut dyn Storage) -> Singleton<'_, Config> {
     singleton(storage, CONFIG_KEY)
 }
 
-pub(crate) fn config_read(storage: &dyn Storage) -> ReadonlySingleton<Config> {
+pub(crate) fn config_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Config> {
     singleton_read(storage, CONFIG_KEY)
 }
 
@@ -29,13 +41,117 @@ pub(crate) fn config_read(storage: &dyn Storage) -> ReadonlySingleton<Config> {
 pub struct ContentRecord {
     pub price: Coin,
     pub content: String,
-    pub owner: HumanAddr,
+    pub owner: Addr,
+    /// Set when the route is held under Harberger tax, in which case `price` is the
+    /// self-assessed price rather than an escrowed amount.
+    #[serde(default)]
+    pub harberger: Option<HarbergerTax>,
+}
+
+/// Prepaid tax for a route held under Harberger tax.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HarbergerTax {
+    /// Remaining prepaid tax, in the denom of the record price.
+    pub balance: Uint128,
+    /// Block height up to which tax has been collected.
+    pub last_accrued: u64,
+}
+
+impl ContentRecord {
+    /// Collects tax owed since the last accrual from the prepaid balance, returning the amount
+    /// collected. Records not held under Harberger tax are never charged.
+    pub fn accrue_tax(&mut self, rate: Decimal, height: u64) -> Uint128 {
+        match &mut self.harberger {
+            Some(tax) => {
+                let blocks = height.saturating_sub(tax.last_accrued);
+                let owed = Uint128::new(tax_owed(self.price.amount.u128(), blocks, rate));
+                let collected = std::cmp::min(owed, tax.balance);
+                tax.balance -= collected;
+                tax.last_accrued = height;
+                collected
+            }
+            None => Uint128::zero(),
+        }
+    }
+
+    /// Returns true if the route is held under Harberger tax and the prepaid tax has run out.
+    pub fn is_foreclosed(&self) -> bool {
+        matches!(&self.harberger, Some(tax) if tax.balance.is_zero())
+    }
 }
 
-pub(crate) fn resolver(storage: &mut dyn Storage) -> Bucket<ContentRecord> {
+/// Fixed point scale of [Decimal], which holds 18 fractional digits.
+const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+
+/// Returns the tax owed on `price` over `blocks` at `rate` per block, saturating at `u128::MAX`
+/// rather than overflowing, as holders can self-assess any price. `rate` must be at most one.
+fn tax_owed(price: u128, blocks: u64, rate: Decimal) -> u128 {
+    let rate = rate.atomics().u128();
+    let blocks = u128::from(blocks);
+
+    // Split the per block tax into whole units and a remainder in 1e-18 units, so no
+    // intermediate product can overflow.
+    let low = (price % DECIMAL_FRACTIONAL) * rate;
+    let per_block = (price / DECIMAL_FRACTIONAL) * rate + low / DECIMAL_FRACTIONAL;
+    let remainder = low % DECIMAL_FRACTIONAL;
+    per_block
+        .saturating_mul(blocks)
+        .saturating_add(remainder * blocks / DECIMAL_FRACTIONAL)
+}
+
+pub(crate) fn resolver(storage: &mut dyn Storage) -> Bucket<'_, ContentRecord> {
     bucket(storage, ROUTE_KEY)
 }
 
-pub(crate) fn resolver_read(storage: &dyn Storage) -> ReadonlyBucket<ContentRecord> {
+pub(crate) fn resolver_read(storage: &dyn Storage) -> ReadonlyBucket<'_, ContentRecord> {
     bucket_read(storage, ROUTE_KEY)
 }
+
+/// Dutch auction listing for an unclaimed route. The ask price decays linearly from
+/// `start_price` to `end_price` over `duration` seconds from `start_time`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Listing {
+    pub start_price: Coin,
+    pub end_price: Coin,
+    pub start_time: u64,
+    pub duration: u64,
+}
+
+impl Listing {
+    /// Returns the ask price at the given block time, in seconds.
+    pub fn price_at(&self, time: u64) -> Coin {
+        let elapsed = time.saturating_sub(self.start_time).min(self.duration);
+        let range = self.start_price.amount - self.end_price.amount;
+        let decayed = range.multiply_ratio(elapsed, self.duration);
+        Coin {
+            denom: self.start_price.denom.clone(),
+            amount: self.start_price.amount - decayed,
+        }
+    }
+}
+
+pub(crate) fn listings(storage: &mut dyn Storage) -> Bucket<'_, Listing> {
+    bucket(storage, LISTING_KEY)
+}
+
+pub(crate) fn listings_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Listing> {
+    bucket_read(storage, LISTING_KEY)
+}
+
+/// Harberger tax and auction proceeds collected for the contract owner.
+pub(crate) fn tax_ledger(storage: &mut dyn Storage) -> Singleton<'_, Vec<Coin>> {
+    singleton(storage, TAX_KEY)
+}
+
+pub(crate) fn tax_ledger_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Vec<Coin>> {
+    singleton_read(storage, TAX_KEY)
+}
+
+/// Contracts notified when a route changes hands.
+pub(crate) fn subscribers(storage: &mut dyn Storage) -> Singleton<'_, Vec<Addr>> {
+    singleton(storage, SUBSCRIBERS_KEY)
+}
+
+pub(crate) fn subscribers_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Vec<Addr>> {
+    singleton_read(storage, SUBSCRIBERS_KEY)
+}
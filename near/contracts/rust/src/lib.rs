@@ -1,143 +1,2808 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
-use near_sdk::{env, near_bindgen, AccountId, Balance, Promise};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    env, ext_contract, near_bindgen, AccountId, Balance, Gas, PanicOnDefault, Promise,
+    PromiseOrValue, PromiseResult, StorageUsage,
+};
 
 #[global_allocator]
 static ALLOC: near_sdk::wee_alloc::WeeAlloc = near_sdk::wee_alloc::WeeAlloc::INIT;
 
-#[derive(BorshDeserialize, BorshSerialize)]
+/// Cost of storing one byte of contract state, 1 NEAR per 100kb.
+const STORAGE_PRICE_PER_BYTE: Balance = 10_000_000_000_000_000_000;
+
+/// Gas attached to the callback checking whether a refund transfer succeeded.
+const GAS_FOR_REFUND_CALLBACK: Gas = 5_000_000_000_000;
+
+/// Gas attached to `ft_transfer` calls refunding token-priced routes.
+const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+
+/// Gas kept by `upgrade` for itself, the rest is attached to the `migrate` call.
+const GAS_FOR_UPGRADE: Gas = 20_000_000_000_000;
+
+/// Storage key near-sdk keeps the contract state under.
+const STATE_KEY: &[u8] = b"STATE";
+
+/// Storage key the version of the state layout is kept under, written by `new` and `migrate`.
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// Version of the current state layout, to be bumped along with any change to it. State without a
+/// stored version is that of the initially deployed contract.
+const STATE_VERSION: u8 = 1;
+
+/// Longest lease the owner can set, ten years in nanoseconds.
+const MAX_LEASE_DURATION: u64 = 10 * 365 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Denominator of the commission rate, which is in basis points.
+const BASIS_POINTS: u16 = 10_000;
+
+/// Highest commission the owner can set, 10% in basis points.
+const MAX_COMMISSION_BPS: u16 = 1_000;
+
+/// Standard and version of the NEP-297 events logged by the contract.
+const EVENT_STANDARD: &str = "plutocratic-hosting";
+const EVENT_VERSION: &str = "1.0.0";
+
+/// Events logged by the contract, following NEP-297.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event<'a> {
+    RoutePurchased {
+        route: &'a str,
+        owner: &'a str,
+        price: U128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        token_id: Option<&'a str>,
+    },
+    RouteOutbid {
+        route: &'a str,
+        previous_owner: &'a str,
+        price: U128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        token_id: Option<&'a str>,
+    },
+    RefundSent {
+        account_id: &'a str,
+        amount: U128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        token_id: Option<&'a str>,
+    },
+    OwnerWithdraw {
+        account_id: &'a str,
+        amount: U128,
+    },
+    ContentUpdated {
+        route: &'a str,
+        editor: &'a str,
+    },
+    OwnerProposed {
+        owner: &'a str,
+        proposed_owner: &'a str,
+    },
+    OwnerChanged {
+        previous_owner: &'a str,
+        owner: &'a str,
+    },
+    LeaseExpired {
+        route: &'a str,
+        previous_owner: &'a str,
+        price: U128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        token_id: Option<&'a str>,
+    },
+    LeaseRenewed {
+        route: &'a str,
+        owner: &'a str,
+        expires_at: U64,
+    },
+    RouteReleased {
+        route: &'a str,
+        owner: &'a str,
+        price: U128,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        token_id: Option<&'a str>,
+    },
+}
+
+impl Event<'_> {
+    /// Logs the event as an `EVENT_JSON:` prefixed NEP-297 log.
+    pub fn emit(&self) {
+        #[derive(Serialize)]
+        #[serde(crate = "near_sdk::serde")]
+        struct EventLog<'a, 'b> {
+            standard: &'static str,
+            version: &'static str,
+            #[serde(flatten)]
+            event: &'a Event<'b>,
+        }
+
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: self,
+        };
+        let json = near_sdk::serde_json::to_string(&log).unwrap();
+        env::log(format!("EVENT_JSON:{}", json).as_bytes());
+    }
+}
+
+#[ext_contract(ext_self)]
+pub trait ExtContentTracker {
+    fn on_refund_complete(&mut self, account_id: AccountId, amount: U128);
+    fn on_ft_refund_complete(&mut self, token_id: AccountId, account_id: AccountId, amount: U128);
+}
+
+#[ext_contract(ext_fungible_token)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
 pub struct ContentRecord {
     pub price: Balance,
     pub content: String,
     pub owner: AccountId,
+    /// Fungible token the price is denominated in, or `None` if priced in NEAR.
+    pub token_id: Option<AccountId>,
+    /// NEAR paid for the storage of the record, refunded along with the price.
+    pub storage_paid: Balance,
+    /// Account approved by the owner to update the content.
+    pub editor: Option<AccountId>,
+    /// Block timestamp in nanoseconds the lease on the route ends at, or `None` if the route was
+    /// purchased while leases were disabled and is held until outbid.
+    pub expires_at: Option<u64>,
+    /// Commission in basis points taken from the price refunded when the route is outbid, the
+    /// contract's rate when it was purchased.
+    pub commission_bps: u16,
 }
 
-#[near_bindgen]
+impl ContentRecord {
+    /// Name of the currency the price is denominated in.
+    fn denomination(&self) -> &str {
+        self.token_id.as_deref().unwrap_or("NEAR")
+    }
+
+    /// Whether the account is allowed to update the content.
+    fn can_edit(&self, account_id: &str) -> bool {
+        self.owner == account_id || self.editor.as_deref() == Some(account_id)
+    }
+
+    /// Whether the lease on the route has ended.
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| env::block_timestamp() >= expires_at)
+    }
+}
+
+/// Layout of route records in the initially deployed contract, kept in a `LookupMap` under the
+/// same `v` prefix the route map uses now.
 #[derive(BorshDeserialize, BorshSerialize)]
-pub struct ContentTracker {
-    values: LookupMap<String, ContentRecord>,
+pub struct ContentRecordV0 {
+    pub price: Balance,
+    pub content: String,
+    pub owner: AccountId,
+}
+
+impl From<ContentRecordV0> for ContentRecord {
+    fn from(record: ContentRecordV0) -> Self {
+        ContentRecord {
+            price: record.price,
+            content: record.content,
+            owner: record.owner,
+            token_id: None,
+            // Storage was paid by the contract rather than the buyer
+            storage_paid: 0,
+            editor: None,
+            expires_at: None,
+            commission_bps: 0,
+        }
+    }
+}
+
+/// Versions of the stored route records. New layouts are added as variants, with older
+/// versions converted to the current [`ContentRecord`] when read.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum VersionedContentRecord {
+    V1(ContentRecord),
+}
+
+impl From<VersionedContentRecord> for ContentRecord {
+    fn from(record: VersionedContentRecord) -> Self {
+        match record {
+            VersionedContentRecord::V1(record) => record,
+        }
+    }
+}
+
+impl From<ContentRecord> for VersionedContentRecord {
+    fn from(record: ContentRecord) -> Self {
+        VersionedContentRecord::V1(record)
+    }
+}
+
+/// Layout of the contract state in the initially deployed contract, read when migrating.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContentTrackerV0 {
+    values: LookupMap<String, ContentRecordV0>,
     contract_owner: AccountId,
 }
 
-impl Default for ContentTracker {
+/// Constraints on purchased routes and their content, set by the contract owner.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Limits {
+    /// Maximum length of a route in bytes.
+    pub max_route_len: u64,
+    /// Maximum length of content in bytes.
+    pub max_content_len: u64,
+    /// Characters routes may consist of, or `None` to allow any.
+    pub allowed_charset: Option<String>,
+}
+
+impl Default for Limits {
     fn default() -> Self {
-        // TODO verify if env is initialized in default call
-        let contract_owner = env::predecessor_account_id();
         Self {
-            values: LookupMap::new(b"v".to_vec()),
-            contract_owner,
+            max_route_len: 256,
+            max_content_len: 32 * 1024,
+            allowed_charset: None,
+        }
+    }
+}
+
+impl Limits {
+    fn check_route(&self, route: &str) {
+        if route.len() as u64 > self.max_route_len {
+            env::panic(
+                format!(
+                    "Route is {} bytes long, the limit is {}",
+                    route.len(),
+                    self.max_route_len
+                )
+                .as_bytes(),
+            );
+        }
+        if let Some(charset) = &self.allowed_charset {
+            if let Some(c) = route.chars().find(|c| !charset.contains(*c)) {
+                env::panic(
+                    format!(
+                        "Route contains {:?}, only characters in {:?} are allowed",
+                        c, charset
+                    )
+                    .as_bytes(),
+                );
+            }
+        }
+    }
+
+    fn check_content(&self, content: &str) {
+        if content.len() as u64 > self.max_content_len {
+            env::panic(
+                format!(
+                    "Content is {} bytes long, the limit is {}",
+                    content.len(),
+                    self.max_content_len
+                )
+                .as_bytes(),
+            );
+        }
+    }
+}
+
+/// Refund owed to an account, in NEAR or the fungible token.
+struct Refund {
+    account_id: AccountId,
+    token_id: Option<AccountId>,
+    amount: Balance,
+}
+
+/// Purchase passed as the `msg` of an `ft_transfer_call` to the contract.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPurchaseMsg {
+    pub route: String,
+    pub content: String,
+}
+
+/// Content, price and owner of a route, as returned from view methods.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContentView {
+    pub content: String,
+    pub price: U128,
+    pub owner: AccountId,
+    pub token_id: Option<AccountId>,
+    pub editor: Option<AccountId>,
+    pub expires_at: Option<U64>,
+}
+
+impl From<ContentRecord> for ContentView {
+    fn from(record: ContentRecord) -> Self {
+        Self {
+            content: record.content,
+            price: record.price.into(),
+            owner: record.owner,
+            token_id: record.token_id,
+            editor: record.editor,
+            expires_at: record.expires_at.map(U64),
         }
     }
 }
 
+/// Route and its record, as returned from view methods.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RouteView {
+    pub route: String,
+    pub content: String,
+    pub price: U128,
+    pub owner: AccountId,
+    pub token_id: Option<AccountId>,
+}
+
+impl RouteView {
+    fn new(route: String, record: ContentRecord) -> Self {
+        Self {
+            route,
+            content: record.content,
+            price: record.price.into(),
+            owner: record.owner,
+            token_id: record.token_id,
+        }
+    }
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct ContentTracker {
+    values: UnorderedMap<String, VersionedContentRecord>,
+    contract_owner: AccountId,
+    /// Refunds which failed to transfer, claimable by the account.
+    refunds: LookupMap<AccountId, Balance>,
+    /// Fungible token contract accepted for purchases through `ft_transfer_call`.
+    ft_token_id: Option<AccountId>,
+    /// Token refunds which failed to transfer, keyed by token and account.
+    ft_refunds: LookupMap<(AccountId, AccountId), Balance>,
+    /// Account proposed by the owner to take over the contract, until it accepts.
+    proposed_owner: Option<AccountId>,
+    /// Length of the lease on purchased routes in nanoseconds, or `None` if routes are held
+    /// until outbid.
+    lease_duration: Option<u64>,
+    /// Constraints checked when routes are purchased and content is updated.
+    limits: Limits,
+    /// Commission in basis points set on NEAR-priced routes when they are purchased, taken from
+    /// the price refunded to their owner when they are outbid.
+    commission_bps: u16,
+    /// Commission collected and not yet withdrawn by the contract owner.
+    fees_collected: Balance,
+    /// Routes held by each account. Buyers of NEAR-priced routes pay for their entry.
+    routes_by_owner: LookupMap<AccountId, UnorderedSet<String>>,
+    /// Whether purchases are stopped by the contract owner.
+    paused: bool,
+    /// NEAR deposited by each account to pay for the storage of token-priced routes it buys.
+    storage_balances: LookupMap<AccountId, Balance>,
+    /// Routes of the initially deployed contract not yet moved into `values`, which can't be
+    /// enumerated. Each one is moved when it's next changed, or by `import_legacy_routes`.
+    legacy_values: Option<LookupMap<String, ContentRecordV0>>,
+    /// Fee charged for each lease renewal, and the lowest bid that takes a NEAR-priced route
+    /// whose lease has expired.
+    lease_base_price: Balance,
+}
+
 #[near_bindgen]
 impl ContentTracker {
+    /// Initializes the contract with the given owner. Must be called once before any other
+    /// method, usually batched with the deploy transaction.
+    #[init]
+    pub fn new(owner_id: AccountId) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        assert!(
+            env::is_valid_account_id(owner_id.as_bytes()),
+            "Invalid owner account id: {}",
+            owner_id
+        );
+        env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+        Self::with_owner(owner_id)
+    }
+
+    /// Migrates the contract state to the current layout, called by `upgrade` after deploying the
+    /// new code. `#[init]` doesn't check for existing state in this version of near-sdk, so this
+    /// can read the old state. The state is read according to its stored version, or as the
+    /// initially deployed layout if no version was stored. Records are converted when read, so
+    /// they are left in place.
+    #[init]
+    #[private]
+    pub fn migrate() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("No contract state to migrate");
+        let contract = match env::storage_read(STATE_VERSION_KEY).as_deref() {
+            Some([STATE_VERSION]) => Self::try_from_slice(&state).expect("Invalid contract state"),
+            Some(version) => env::panic(format!("Unknown state version {:?}", version).as_bytes()),
+            None => Self::migrate_v0(&state),
+        };
+        env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+        contract
+    }
+
+    /// Deploys new code to the contract and migrates the state to its layout. Only callable by
+    /// the contract owner, with the code passed as base64.
+    pub fn upgrade(&self, code: Base64VecU8) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        let migrate_gas = (env::prepaid_gas() - env::used_gas())
+            .checked_sub(GAS_FOR_UPGRADE)
+            .expect("Not enough gas attached to migrate");
+        Promise::new(env::current_account_id())
+            .deploy_contract(code.into())
+            .function_call(b"migrate".to_vec(), Vec::new(), 0, migrate_gas)
+    }
+
+    /// Gets the owner of the contract.
+    pub fn get_owner(&self) -> AccountId {
+        self.contract_owner.clone()
+    }
+
+    /// Proposes an account to take over ownership of the contract, replacing any previous
+    /// proposal. Ownership only changes once the account calls `accept_owner`.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        assert!(
+            env::is_valid_account_id(new_owner.as_bytes()),
+            "Invalid owner account id: {}",
+            new_owner
+        );
+        Event::OwnerProposed {
+            owner: &self.contract_owner,
+            proposed_owner: &new_owner,
+        }
+        .emit();
+        self.proposed_owner = Some(new_owner);
+    }
+
+    /// Accepts ownership of the contract, callable by the account proposed by the owner.
+    pub fn accept_owner(&mut self) {
+        let new_owner = env::predecessor_account_id();
+        assert!(
+            self.proposed_owner.as_ref() == Some(&new_owner),
+            "Only the proposed owner can accept ownership"
+        );
+        Event::OwnerChanged {
+            previous_owner: &self.contract_owner,
+            owner: &new_owner,
+        }
+        .emit();
+        self.contract_owner = new_owner;
+        self.proposed_owner = None;
+    }
+
     /// Gets content at a given route.
     pub fn get_route(&self, route: String) -> Option<String> {
-        self.values.get(&route).map(|v| v.content)
+        self.get_record(&route).map(|v| v.content)
+    }
+
+    /// Gets content at a given route, along with its price and owner.
+    pub fn get_route_info(&self, route: String) -> Option<ContentView> {
+        self.get_record(&route).map(ContentView::from)
+    }
+
+    /// Gets the number of purchased routes.
+    pub fn get_num_routes(&self) -> u64 {
+        self.values.len()
+    }
+
+    /// Gets up to `limit` routes starting at `from_index`, in storage order.
+    pub fn get_routes(&self, from_index: u64, limit: u64) -> Vec<RouteView> {
+        let keys = self.values.keys_as_vector();
+        let values = self.values.values_as_vector();
+        let end = from_index.saturating_add(limit).min(keys.len());
+        (from_index..end)
+            .map(|index| {
+                let record = ContentRecord::from(values.get(index).unwrap());
+                RouteView::new(keys.get(index).unwrap(), record)
+            })
+            .collect()
+    }
+
+    /// Gets up to `limit` routes held by the account starting at `from_index`.
+    pub fn get_routes_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<RouteView> {
+        let routes = match self.routes_by_owner.get(&account_id) {
+            Some(routes) => routes,
+            None => return Vec::new(),
+        };
+        let routes = routes.as_vector();
+        let end = from_index.saturating_add(limit).min(routes.len());
+        (from_index..end)
+            .map(|index| {
+                let route = routes.get(index).unwrap();
+                let record = self.get_record(&route).unwrap();
+                RouteView::new(route, record)
+            })
+            .collect()
     }
 
-    /// Purchases a route based on funds sent to the contract.
+    /// Purchases a route based on funds sent to the contract. The deposit must cover the storage
+    /// used by the route, with the remainder being the bid for the route.
     #[payable]
     pub fn purchase(&mut self, route: String, content: String) {
         let deposit = env::attached_deposit();
         assert!(deposit > 0);
-        if let Some(entry) = self.values.get(&route) {
-            assert!(
-                deposit > entry.price,
-                "Not enough deposit to purchase route, price: {} deposit: {}",
-                entry.price,
-                deposit
-            );
+        self.internal_purchase(
+            route,
+            ContentRecord {
+                price: deposit,
+                content,
+                owner: env::predecessor_account_id(),
+                token_id: None,
+                storage_paid: 0,
+                editor: None,
+                expires_at: None,
+                commission_bps: 0,
+            },
+        );
+    }
+
+    /// Purchases several routes with one deposit. Each item is a route, its content and the part
+    /// of the deposit bid for it, which like in `purchase` covers the storage of the route. Refunds
+    /// owed to the same account are merged into one transfer, and any deposit not bid is returned
+    /// to the caller.
+    #[payable]
+    pub fn purchase_many(&mut self, items: Vec<(String, String, U128)>) {
+        assert!(!items.is_empty(), "No routes to purchase");
+        let buyer = env::predecessor_account_id();
+        let mut remaining = env::attached_deposit();
+        let mut refunds: Vec<Refund> = Vec::new();
 
-            // Refund purchase to existing owner
-            Promise::new(entry.owner).transfer(entry.price);
+        for (route, content, bid) in items {
+            assert!(bid.0 > 0, "Bid for route {} must be positive", route);
+            remaining = remaining
+                .checked_sub(bid.0)
+                .unwrap_or_else(|| env::panic(b"Bids add up to more than the attached deposit"));
+            let mut record = ContentRecord {
+                price: bid.0,
+                content,
+                owner: buyer.clone(),
+                token_id: None,
+                storage_paid: 0,
+                editor: None,
+                expires_at: None,
+                commission_bps: 0,
+            };
+            if let Some(refund) = self.replace_record(&route, &mut record) {
+                merge_refund(&mut refunds, refund);
+            }
+            Event::RoutePurchased {
+                route: &route,
+                owner: &record.owner,
+                price: record.price.into(),
+                token_id: None,
+            }
+            .emit();
         }
 
-        // Update record for the contract state.
-        self.values.insert(
-            &route,
-            &ContentRecord {
-                price: deposit,
+        if remaining > 0 {
+            merge_refund(
+                &mut refunds,
+                Refund {
+                    account_id: buyer,
+                    token_id: None,
+                    amount: remaining,
+                },
+            );
+        }
+        for refund in refunds {
+            self.send_refund(refund);
+        }
+    }
+
+    /// Purchases a route with fungible tokens, called by the token contract on
+    /// `ft_transfer_call`. The `msg` is a JSON [`FtPurchaseMsg`], and the full amount is the bid
+    /// for the route. Storage for the route is paid in NEAR from the sender's storage balance,
+    /// topped up with `storage_deposit`.
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        assert!(
+            self.ft_token_id.as_ref() == Some(&token_id),
+            "Token {} is not accepted for purchases",
+            token_id
+        );
+        assert!(amount.0 > 0);
+        let FtPurchaseMsg { route, content } = near_sdk::serde_json::from_str(&msg)
+            .expect("Invalid purchase msg, expected {\"route\": .., \"content\": ..}");
+
+        // Panicking above or within the purchase refunds the full amount to the sender
+        self.internal_purchase(
+            route,
+            ContentRecord {
+                price: amount.0,
                 content,
-                owner: env::predecessor_account_id(),
+                owner: sender_id,
+                token_id: Some(token_id),
+                storage_paid: 0,
+                editor: None,
+                expires_at: None,
+                commission_bps: 0,
             },
         );
+        PromiseOrValue::Value(U128(0))
     }
 
-    /// Allows owner of the contract withdraw funds.
-    pub fn withdraw(&mut self) {
+    /// Adds the attached deposit to the storage balance of the account, or the caller if not
+    /// given, which pays for the storage of token-priced routes it buys.
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>) -> U128 {
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit > 0,
+            "Attach a deposit to add to the storage balance"
+        );
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        assert!(
+            env::is_valid_account_id(account_id.as_bytes()),
+            "Invalid account id: {}",
+            account_id
+        );
+        let balance = self.storage_balances.get(&account_id).unwrap_or(0) + deposit;
+        self.storage_balances.insert(&account_id, &balance);
+        balance.into()
+    }
+
+    /// Gets the storage balance of the account not yet used by its token-priced routes.
+    pub fn storage_balance_of(&self, account_id: AccountId) -> U128 {
+        self.storage_balances.get(&account_id).unwrap_or(0).into()
+    }
+
+    /// Transfers the caller's unused storage balance back to it.
+    pub fn storage_withdraw(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .storage_balances
+            .remove(&account_id)
+            .expect("No storage balance to withdraw");
+        self.refund(account_id, amount)
+    }
+
+    /// Sets the fungible token accepted for purchases, or disables token purchases with `None`.
+    /// Routes already priced in a token keep their denomination.
+    pub fn set_ft_token(&mut self, token_id: Option<AccountId>) {
         assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        if let Some(token_id) = &token_id {
+            assert!(
+                env::is_valid_account_id(token_id.as_bytes()),
+                "Invalid token account id: {}",
+                token_id
+            );
+        }
+        self.ft_token_id = token_id;
+    }
 
-        // Send the contract funds to the contract owner
-        Promise::new(self.contract_owner.clone()).transfer(env::account_balance());
+    /// Gets the fungible token accepted for purchases.
+    pub fn get_ft_token(&self) -> Option<AccountId> {
+        self.ft_token_id.clone()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::MockedBlockchain;
-    use near_sdk::{test_utils::VMContextBuilder, testing_env, VMContext};
+    /// Sets the length of the lease on routes purchased from now on, in nanoseconds, or disables
+    /// leases with `None`. Existing leases keep their expiry.
+    pub fn set_lease_duration(&mut self, duration: Option<U64>) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        if let Some(duration) = duration {
+            assert!(duration.0 > 0, "Lease duration must be positive");
+            assert!(
+                duration.0 <= MAX_LEASE_DURATION,
+                "Lease duration can't be more than {} nanoseconds",
+                MAX_LEASE_DURATION
+            );
+        }
+        self.lease_duration = duration.map(u64::from);
+    }
 
-    fn get_context(name: impl ToString, is_view: bool) -> VMContext {
-        VMContextBuilder::new()
-            .predecessor_account_id(name.to_string())
-            .is_view(is_view)
-            .build()
+    /// Gets the length of the lease on purchased routes, in nanoseconds.
+    pub fn get_lease_duration(&self) -> Option<U64> {
+        self.lease_duration.map(U64)
     }
 
-    #[test]
-    fn basic_initialize() {
-        let context = get_context("bob", false);
-        testing_env!(context);
-        let contract = ContentTracker::default();
-        assert!(contract.get_route("test".to_string()).is_none());
+    /// Sets the fee charged for renewing a lease, which is also the lowest bid that takes a
+    /// NEAR-priced route whose lease has expired.
+    pub fn set_lease_base_price(&mut self, base_price: U128) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        self.lease_base_price = base_price.into();
     }
 
-    // * this is inconsistently failing tests to a seg fault
-    // #[test]
-    // // #[should_panic]
-    // fn no_deposit_fail() {
-    //     let context = get_context("bob", false);
-    //     testing_env!(context);
-    //     let mut contract = ContentTracker::default();
+    /// Gets the fee charged for renewing a lease.
+    pub fn get_lease_base_price(&self) -> U128 {
+        self.lease_base_price.into()
+    }
 
-    //     // Should fail because no deposit attached
-    //     std::panic::catch_unwind(move || {
-    //         contract.purchase("troute".to_string(), "tcontent".to_string());
-    //     })
-    //     .unwrap_err();
-    // }
+    /// Sets the constraints on routes purchased and content updated from now on.
+    pub fn set_limits(
+        &mut self,
+        max_route_len: u64,
+        max_content_len: u64,
+        allowed_charset: Option<String>,
+    ) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        self.limits = Limits {
+            max_route_len,
+            max_content_len,
+            allowed_charset,
+        };
+    }
 
-    #[test]
-    fn purchase_and_replace() {
-        let mut context = get_context("bob", false);
-        println!("name: {}", context.signer_account_id);
-        testing_env!(context.clone());
-        let mut contract = ContentTracker::default();
+    /// Gets the constraints on routes and their content.
+    pub fn get_limits(&self) -> Limits {
+        self.limits.clone()
+    }
 
-        context.attached_deposit = 2;
-        testing_env!(context.clone());
-        contract.purchase("troute".to_string(), "tcontent".to_string());
+    /// Sets the commission in basis points taken from refunds to previous owners of NEAR-priced
+    /// routes purchased from now on, when they are outbid. Existing routes keep their rate.
+    pub fn set_commission(&mut self, commission_bps: u16) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        assert!(
+            commission_bps <= MAX_COMMISSION_BPS,
+            "Commission of {} basis points is over the maximum of {}",
+            commission_bps,
+            MAX_COMMISSION_BPS
+        );
+        self.commission_bps = commission_bps;
+    }
+
+    /// Gets the commission in basis points set on routes purchased from now on.
+    pub fn get_commission(&self) -> u16 {
+        self.commission_bps
+    }
+
+    /// Gets the commission collected and not yet withdrawn.
+    pub fn get_fees_collected(&self) -> U128 {
+        self.fees_collected.into()
+    }
+
+    /// Extends the lease on a route owned by the caller by the lease duration, from its expiry or
+    /// from now if it has already expired. The attached deposit must cover the lease base price,
+    /// and is collected as a fee.
+    #[payable]
+    pub fn renew(&mut self, route: String) {
+        let deposit = env::attached_deposit();
+        self.import_legacy_route(&route);
+        let mut record = self.get_record(&route).expect("Route not found");
         assert_eq!(
-            contract.get_route("troute".to_string()),
-            Some("tcontent".to_string())
+            env::predecessor_account_id(),
+            record.owner,
+            "Only the route owner can renew it"
+        );
+        assert!(
+            record.token_id.is_none(),
+            "Leases on token-priced routes can't be renewed with NEAR"
+        );
+        let expires_at = record.expires_at.expect("Route is not leased");
+        let lease_duration = self.lease_duration.expect("Leases are disabled");
+        assert!(
+            self.lease_base_price > 0,
+            "Renewals are disabled until a lease base price is set"
+        );
+        assert!(
+            deposit >= self.lease_base_price,
+            "Deposit of {} does not cover the lease base price of {}",
+            deposit,
+            self.lease_base_price
         );
 
-        // Try purchasing same route with same amount
-        context.predecessor_account_id = "alice".to_string();
-        context.attached_deposit = 3;
-        testing_env!(context.clone());
-        contract.purchase("troute".to_string(), "new content".to_string());
+        let expires_at = expires_at
+            .max(env::block_timestamp())
+            .checked_add(lease_duration)
+            .expect("Lease expiry overflowed");
+        record.expires_at = Some(expires_at);
+        self.fees_collected = self
+            .fees_collected
+            .checked_add(deposit)
+            .expect("Fees collected overflowed");
+        // The record keeps its size, so the storage paid still covers it
+        self.values
+            .insert(&route, &VersionedContentRecord::from(record.clone()));
+        Event::LeaseRenewed {
+            route: &route,
+            owner: &record.owner,
+            expires_at: record.expires_at.unwrap().into(),
+        }
+        .emit();
+    }
+
+    /// Updates the content at a route, callable by the route owner or its approved editor. Any
+    /// deposit is added to the storage paid for a NEAR-priced route, which must cover the
+    /// updated content if it uses more storage.
+    #[payable]
+    pub fn update_content(&mut self, route: String, content: String) {
+        let editor = env::predecessor_account_id();
+        let mut record = self.get_record(&route).expect("Route not found");
+        assert!(
+            record.can_edit(&editor),
+            "Only the route owner or its editor can update content"
+        );
+        self.limits.check_content(&content);
+        record.content = content;
+        self.update_record(&route, record);
+        Event::ContentUpdated {
+            route: &route,
+            editor: &editor,
+        }
+        .emit();
+    }
+
+    /// Approves an account to update the content of a route owned by the caller, replacing any
+    /// previous editor. The editor has no authority over funds or ownership of the route.
+    #[payable]
+    pub fn approve_editor(&mut self, route: String, account_id: AccountId) {
+        let mut record = self.get_record(&route).expect("Route not found");
         assert_eq!(
-            contract.get_route("troute".to_string()),
-            Some("new content".to_string())
+            env::predecessor_account_id(),
+            record.owner,
+            "Only the route owner can approve editors"
         );
+        assert!(
+            env::is_valid_account_id(account_id.as_bytes()),
+            "Invalid editor account id: {}",
+            account_id
+        );
+        record.editor = Some(account_id);
+        self.update_record(&route, record);
+    }
 
-        // Contract owner
-        context.predecessor_account_id = "bob".to_string();
-        testing_env!(context.clone());
-        contract.withdraw();
+    /// Revokes the editor of a route owned by the caller.
+    pub fn revoke_editor(&mut self, route: String) {
+        let mut record = self.get_record(&route).expect("Route not found");
+        assert_eq!(
+            env::predecessor_account_id(),
+            record.owner,
+            "Only the route owner can revoke editors"
+        );
+        record.editor = None;
+        self.update_record(&route, record);
+    }
+
+    /// Gives up a route owned by the caller, removing it and refunding its price along with the
+    /// storage paid for it. Storage paid for token-priced routes goes back to the storage balance.
+    pub fn release(&mut self, route: String) -> Promise {
+        self.import_legacy_route(&route);
+        let record = self.get_record(&route).expect("Route not found");
+        assert_eq!(
+            env::predecessor_account_id(),
+            record.owner,
+            "Only the route owner can release it"
+        );
+        self.values.remove(&route);
+        self.unindex_route(&record.owner, &route);
+        Event::RouteReleased {
+            route: &route,
+            owner: &record.owner,
+            price: record.price.into(),
+            token_id: record.token_id.as_deref(),
+        }
+        .emit();
+
+        match record.token_id {
+            Some(token_id) => {
+                self.credit_storage(&record.owner, record.storage_paid);
+                self.ft_refund(token_id, record.owner, record.price)
+            }
+            None => self.refund(record.owner, record.price + record.storage_paid),
+        }
+    }
+
+    /// Moves routes of the initially deployed contract into the route map, so they are listed and
+    /// wound down along with the others. Routes are otherwise moved when they are next changed.
+    /// Only callable by the contract owner, with the routes found off-chain, such as from past
+    /// `purchase` calls. Returns the number of routes moved.
+    pub fn import_legacy_routes(&mut self, routes: Vec<String>) -> u64 {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        routes
+            .iter()
+            .filter(|route| self.import_legacy_route(route))
+            .count() as u64
+    }
+
+    /// Stops or resumes purchases of routes. Holders can still update, renew and release routes
+    /// while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        self.paused = paused;
+    }
+
+    /// Gets whether purchases are stopped.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Removes up to `limit` routes starting at `from_index`, refunding their price and storage
+    /// paid to the holders, so the contract can be retired. Storage paid for token-priced routes
+    /// goes back to the storage balance, withdrawable with `storage_withdraw`. Only callable by
+    /// the contract owner while paused. Removed routes are replaced by routes from the end, so
+    /// calling with the same `from_index` until none are left refunds them all. Returns the
+    /// number of routes left, which doesn't count routes of the initially deployed contract still
+    /// in the legacy map. Those aren't refunded until they are moved with `import_legacy_routes`.
+    pub fn wind_down(&mut self, from_index: u64, limit: u64) -> u64 {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        assert!(self.paused, "Pause the contract before winding down");
+
+        let keys = self.values.keys_as_vector();
+        let end = from_index.saturating_add(limit).min(keys.len());
+        let routes: Vec<String> = (from_index..end).map(|i| keys.get(i).unwrap()).collect();
+        let mut refunds: Vec<Refund> = Vec::new();
+        for route in routes {
+            let record = ContentRecord::from(self.values.remove(&route).unwrap());
+            self.unindex_route(&record.owner, &route);
+            let amount = match record.token_id {
+                Some(_) => {
+                    self.credit_storage(&record.owner, record.storage_paid);
+                    record.price
+                }
+                None => record.price + record.storage_paid,
+            };
+            merge_refund(
+                &mut refunds,
+                Refund {
+                    account_id: record.owner,
+                    token_id: record.token_id,
+                    amount,
+                },
+            );
+        }
+        for refund in refunds {
+            self.send_refund(refund);
+        }
+        self.values.len()
+    }
+
+    /// Transfers the commission collected to the contract owner. Prices and storage deposits held
+    /// for routes are never withdrawn.
+    pub fn withdraw(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.contract_owner);
+        assert!(self.fees_collected > 0, "No fees to withdraw");
+
+        let amount = std::mem::take(&mut self.fees_collected);
+        Event::OwnerWithdraw {
+            account_id: &self.contract_owner,
+            amount: amount.into(),
+        }
+        .emit();
+        Promise::new(self.contract_owner.clone()).transfer(amount);
+    }
+
+    /// Gets the amount of failed refunds the account can claim.
+    pub fn get_claimable_refund(&self, account_id: AccountId) -> U128 {
+        self.refunds.get(&account_id).unwrap_or(0).into()
+    }
+
+    /// Transfers refunds which previously failed to the caller.
+    pub fn claim_refund(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .refunds
+            .remove(&account_id)
+            .expect("No refund to claim");
+        self.refund(account_id, amount)
+    }
+
+    /// Gets the amount of failed token refunds the account can claim.
+    pub fn get_claimable_ft_refund(&self, token_id: AccountId, account_id: AccountId) -> U128 {
+        self.ft_refunds
+            .get(&(token_id, account_id))
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Transfers token refunds which previously failed to the caller.
+    pub fn claim_ft_refund(&mut self, token_id: AccountId) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .ft_refunds
+            .remove(&(token_id.clone(), account_id.clone()))
+            .expect("No refund to claim");
+        self.ft_refund(token_id, account_id, amount)
+    }
+
+    /// Callback after a refund transfer, crediting the refund to be claimed if it failed.
+    #[private]
+    pub fn on_refund_complete(&mut self, account_id: AccountId, amount: U128) {
+        assert_eq!(env::promise_results_count(), 1, "This is a callback method");
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+
+        let claimable = self.refunds.get(&account_id).unwrap_or(0);
+        self.refunds.insert(&account_id, &(claimable + amount.0));
+    }
+
+    /// Callback after a token refund, crediting the refund to be claimed if it failed.
+    #[private]
+    pub fn on_ft_refund_complete(
+        &mut self,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: U128,
+    ) {
+        assert_eq!(env::promise_results_count(), 1, "This is a callback method");
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+
+        let key = (token_id, account_id);
+        let claimable = self.ft_refunds.get(&key).unwrap_or(0);
+        self.ft_refunds.insert(&key, &(claimable + amount.0));
+    }
+
+    /// Converts the state of the initially deployed contract. Its routes are left in the legacy
+    /// map until they are moved.
+    fn migrate_v0(state: &[u8]) -> Self {
+        let old = ContentTrackerV0::try_from_slice(state).expect("Invalid contract state");
+        let mut contract = Self::with_owner(old.contract_owner);
+        contract.legacy_values = Some(old.values);
+        contract
+    }
+
+    /// State with the given owner and defaults for everything else.
+    fn with_owner(contract_owner: AccountId) -> Self {
+        Self {
+            values: UnorderedMap::new(b"v".to_vec()),
+            contract_owner,
+            refunds: LookupMap::new(b"r".to_vec()),
+            ft_token_id: None,
+            ft_refunds: LookupMap::new(b"f".to_vec()),
+            proposed_owner: None,
+            lease_duration: None,
+            limits: Limits::default(),
+            commission_bps: 0,
+            fees_collected: 0,
+            routes_by_owner: LookupMap::new(b"i".to_vec()),
+            paused: false,
+            storage_balances: LookupMap::new(b"s".to_vec()),
+            legacy_values: None,
+            lease_base_price: 0,
+        }
+    }
+
+    /// Gets the record at a route, converted to the current layout.
+    fn get_record(&self, route: &str) -> Option<ContentRecord> {
+        let route = route.to_string();
+        self.values
+            .get(&route)
+            .map(ContentRecord::from)
+            .or_else(|| {
+                let legacy_values = self.legacy_values.as_ref()?;
+                legacy_values.get(&route).map(ContentRecord::from)
+            })
+    }
+
+    /// Moves the route from the legacy map of the initially deployed contract into `values`, if
+    /// it's still there, returning whether it was moved. Called before changing a route, so it
+    /// isn't left behind in the legacy map.
+    fn import_legacy_route(&mut self, route: &String) -> bool {
+        let record = match self.legacy_values.as_mut().and_then(|v| v.remove(route)) {
+            Some(record) => ContentRecord::from(record),
+            None => return false,
+        };
+        self.index_route(&record.owner, route);
+        self.values
+            .insert(route, &VersionedContentRecord::from(record));
+        true
+    }
+
+    /// Stores changes to the record at a route by its owner, adding any attached deposit to the
+    /// storage paid. Records must have paid for any storage they grow to use, along with their
+    /// entry in the owner index.
+    fn update_record(&mut self, route: &str, mut record: ContentRecord) {
+        record.storage_paid += env::attached_deposit();
+
+        let route = route.to_string();
+        self.import_legacy_route(&route);
+        let initial_storage = env::storage_usage();
+        self.values.remove(&route);
+        self.unindex_route(&record.owner, &route);
+        let freed_storage = initial_storage - env::storage_usage();
+        let storage_paid = record.storage_paid;
+
+        let base_storage = env::storage_usage();
+        self.index_route(&record.owner, &route);
+        self.values
+            .insert(&route, &VersionedContentRecord::from(record));
+        let used_storage = env::storage_usage() - base_storage;
+        if used_storage > freed_storage {
+            let storage_fee = storage_cost(used_storage);
+            assert!(
+                storage_paid >= storage_fee,
+                "Updated route needs {} for storage but {} was paid, attach a deposit to cover it",
+                storage_fee,
+                storage_paid
+            );
+        }
+    }
+
+    /// Replaces the record at a route, refunding the previous owner.
+    fn internal_purchase(&mut self, route: String, mut record: ContentRecord) {
+        if let Some(refund) = self.replace_record(&route, &mut record) {
+            self.send_refund(refund);
+        }
+        Event::RoutePurchased {
+            route: &route,
+            owner: &record.owner,
+            price: record.price.into(),
+            token_id: record.token_id.as_deref(),
+        }
+        .emit();
+    }
+
+    /// Replaces the record at a route, returning the refund owed to the previous owner.
+    /// NEAR-priced records pay for their storage out of the price, which is deducted from the
+    /// record, and token-priced records out of the buyer's storage balance, which the previous
+    /// owner's storage paid is returned to. Routes with an expired lease can be taken for the
+    /// lease base price, or any price if they are token-priced.
+    fn replace_record(&mut self, route: &str, record: &mut ContentRecord) -> Option<Refund> {
+        assert!(!self.paused, "Purchases are paused");
+        let route = route.to_string();
+        self.limits.check_route(&route);
+        self.limits.check_content(&record.content);
+        record.expires_at = self.lease_duration.map(|duration| {
+            env::block_timestamp()
+                .checked_add(duration)
+                .expect("Lease expiry overflowed")
+        });
+        record.commission_bps = self.commission_bps;
+
+        // Remove the existing record first, so the storage used by the new one can be measured
+        self.import_legacy_route(&route);
+        let existing = self.values.remove(&route).map(ContentRecord::from);
+        if let Some(entry) = &existing {
+            self.unindex_route(&entry.owner, &route);
+        }
+
+        let base_storage = env::storage_usage();
+        self.values
+            .insert(&route, &VersionedContentRecord::from(record.clone()));
+        self.index_route(&record.owner, &route);
+        let storage_fee = storage_cost(env::storage_usage() - base_storage);
+        if record.token_id.is_none() {
+            assert!(
+                record.price > storage_fee,
+                "Deposit of {} does not cover the storage cost of {} for the route",
+                record.price,
+                storage_fee
+            );
+            record.price -= storage_fee;
+        } else {
+            let balance = self.storage_balances.get(&record.owner).unwrap_or(0);
+            let remaining = balance.checked_sub(storage_fee).unwrap_or_else(|| {
+                env::panic(
+                    format!(
+                        "Storage balance of {} does not cover the storage cost of {} for the route, \
+                         call storage_deposit first",
+                        balance, storage_fee
+                    )
+                    .as_bytes(),
+                )
+            });
+            self.storage_balances.insert(&record.owner, &remaining);
+        }
+        record.storage_paid = storage_fee;
+
+        let refund = existing.map(|entry| {
+            if entry.is_expired() {
+                assert!(
+                    record.token_id.is_some() || record.price >= self.lease_base_price,
+                    "Not enough deposit to purchase expired route, base price: {} bid: {}",
+                    self.lease_base_price,
+                    record.price
+                );
+                Event::LeaseExpired {
+                    route: &route,
+                    previous_owner: &entry.owner,
+                    price: entry.price.into(),
+                    token_id: entry.token_id.as_deref(),
+                }
+                .emit();
+            } else {
+                assert!(
+                    entry.token_id == record.token_id,
+                    "Route is priced in {}, cannot purchase with {}",
+                    entry.denomination(),
+                    record.denomination()
+                );
+                assert!(
+                    record.price > entry.price,
+                    "Not enough deposit to purchase route, price: {} bid: {}",
+                    entry.price,
+                    record.price
+                );
+
+                Event::RouteOutbid {
+                    route: &route,
+                    previous_owner: &entry.owner,
+                    price: entry.price.into(),
+                    token_id: entry.token_id.as_deref(),
+                }
+                .emit();
+            }
+
+            // Refund purchase, and storage if paid, to existing owner, less the commission
+            let amount = match entry.token_id {
+                Some(_) => {
+                    self.credit_storage(&entry.owner, entry.storage_paid);
+                    entry.price
+                }
+                None => {
+                    let fee = commission(entry.price, entry.commission_bps);
+                    self.fees_collected = self
+                        .fees_collected
+                        .checked_add(fee)
+                        .expect("Fees collected overflowed");
+                    entry.price - fee + entry.storage_paid
+                }
+            };
+            Refund {
+                account_id: entry.owner,
+                token_id: entry.token_id,
+                amount,
+            }
+        });
+
+        // Update record with the bid, balances are fixed size so storage used doesn't change.
+        self.values
+            .insert(&route, &VersionedContentRecord::from(record.clone()));
+        refund
+    }
+
+    /// Adds the route to those held by the account.
+    fn index_route(&mut self, account_id: &AccountId, route: &String) {
+        let mut routes = self.routes_by_owner.get(account_id).unwrap_or_else(|| {
+            let mut prefix = b"o".to_vec();
+            prefix.extend(env::sha256(account_id.as_bytes()));
+            UnorderedSet::new(prefix)
+        });
+        routes.insert(route);
+        self.routes_by_owner.insert(account_id, &routes);
+    }
+
+    /// Removes the route from those held by the account, dropping the account once it holds none.
+    fn unindex_route(&mut self, account_id: &AccountId, route: &String) {
+        if let Some(mut routes) = self.routes_by_owner.get(account_id) {
+            routes.remove(route);
+            if routes.is_empty() {
+                self.routes_by_owner.remove(account_id);
+            } else {
+                self.routes_by_owner.insert(account_id, &routes);
+            }
+        }
+    }
+
+    /// Returns storage paid for a token-priced route to the account's storage balance.
+    fn credit_storage(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount > 0 {
+            let balance = self.storage_balances.get(account_id).unwrap_or(0) + amount;
+            self.storage_balances.insert(account_id, &balance);
+        }
+    }
+
+    /// Transfers a refund in the currency it's owed in.
+    fn send_refund(&self, refund: Refund) -> Promise {
+        match refund.token_id {
+            Some(token_id) => self.ft_refund(token_id, refund.account_id, refund.amount),
+            None => self.refund(refund.account_id, refund.amount),
+        }
+    }
+
+    /// Transfers a refund, which is credited to the account if the transfer fails.
+    fn refund(&self, account_id: AccountId, amount: Balance) -> Promise {
+        Event::RefundSent {
+            account_id: &account_id,
+            amount: amount.into(),
+            token_id: None,
+        }
+        .emit();
+        Promise::new(account_id.clone())
+            .transfer(amount)
+            .then(ext_self::on_refund_complete(
+                account_id,
+                amount.into(),
+                &env::current_account_id(),
+                0,
+                GAS_FOR_REFUND_CALLBACK,
+            ))
+    }
+
+    /// Transfers a token refund, which is credited to the account if the transfer fails.
+    fn ft_refund(&self, token_id: AccountId, account_id: AccountId, amount: Balance) -> Promise {
+        Event::RefundSent {
+            account_id: &account_id,
+            amount: amount.into(),
+            token_id: Some(&token_id),
+        }
+        .emit();
+        ext_fungible_token::ft_transfer(
+            account_id.clone(),
+            amount.into(),
+            None,
+            &token_id,
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::on_ft_refund_complete(
+            token_id,
+            account_id,
+            amount.into(),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_REFUND_CALLBACK,
+        ))
+    }
+}
+
+/// Cost of storing the given number of bytes.
+fn storage_cost(bytes: StorageUsage) -> Balance {
+    Balance::from(bytes) * STORAGE_PRICE_PER_BYTE
+}
+
+/// Adds the refund to the one owed to the same account in the same currency, if any.
+fn merge_refund(refunds: &mut Vec<Refund>, refund: Refund) {
+    match refunds
+        .iter_mut()
+        .find(|r| r.account_id == refund.account_id && r.token_id == refund.token_id)
+    {
+        Some(existing) => existing.amount += refund.amount,
+        None => refunds.push(refund),
+    }
+}
+
+/// Commission in basis points of a price, rounded down. Split so the multiplication can't overflow.
+fn commission(price: Balance, commission_bps: u16) -> Balance {
+    let bps = Balance::from(commission_bps);
+    let denominator = Balance::from(BASIS_POINTS);
+    price / denominator * bps + price % denominator * bps / denominator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::MockedBlockchain;
+    use near_sdk::{testing_env, VMContext};
+
+    const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+    fn get_context(name: impl ToString, is_view: bool) -> VMContext {
+        VMContextBuilder::new()
+            .predecessor_account_id(name.to_string())
+            .is_view(is_view)
+            .build()
+    }
+
+    #[test]
+    fn basic_initialize() {
+        let context = get_context("bob", false);
+        testing_env!(context);
+        let contract = ContentTracker::new("bob".to_string());
+        assert!(contract.get_route("test".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is not initialized")]
+    fn default_panics() {
+        let context = get_context("bob", false);
+        testing_env!(context);
+        let _contract = ContentTracker::default();
+    }
+
+    #[test]
+    #[should_panic(expected = "Already initialized")]
+    fn init_only_once() {
+        let context = get_context("mallory", false);
+        testing_env!(context);
+        let contract = ContentTracker::new("bob".to_string());
+        env::state_write(&contract);
+
+        // First caller after deployment can't reinitialize with themselves as owner
+        ContentTracker::new("mallory".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn first_caller_not_owner() {
+        let context = get_context("bob", false);
+        testing_env!(context);
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        // First caller after initialization doesn't become the owner
+        let context = get_context("mallory", false);
+        testing_env!(context);
+        contract.withdraw();
+    }
+
+    #[test]
+    fn purchase_and_replace() {
+        let mut context = get_context("bob", false);
+        println!("name: {}", context.signer_account_id);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_commission(100);
+
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+        assert_eq!(
+            contract.get_route("troute".to_string()),
+            Some("tcontent".to_string())
+        );
+
+        // Try purchasing same route with same amount
+        context.predecessor_account_id = "alice".to_string();
+        context.attached_deposit = 3 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "new content".to_string());
+        assert_eq!(
+            contract.get_route("troute".to_string()),
+            Some("new content".to_string())
+        );
+
+        // Contract owner
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.withdraw();
+    }
+
+    #[test]
+    fn failed_refund_claimable() {
+        let mut context = get_context("alice.near", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        // Successful transfers aren't credited
+        testing_env!(
+            context.clone(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        contract.on_refund_complete("carol".to_string(), U128(2));
+        assert_eq!(contract.get_claimable_refund("carol".to_string()), U128(0));
+
+        testing_env!(
+            context.clone(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_refund_complete("carol".to_string(), U128(2));
+        contract.on_refund_complete("carol".to_string(), U128(3));
+        assert_eq!(contract.get_claimable_refund("carol".to_string()), U128(5));
+
+        context.predecessor_account_id = "carol".to_string();
+        testing_env!(context);
+        contract.claim_refund();
+        assert_eq!(contract.get_claimable_refund("carol".to_string()), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "No refund to claim")]
+    fn claim_without_refund() {
+        let context = get_context("carol", false);
+        testing_env!(context);
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.claim_refund();
+    }
+
+    #[test]
+    fn purchase_charges_storage() {
+        let mut context = get_context("alice", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        // Storage usage is reset by `testing_env!`, carry it over between calls
+        let initial_storage = env::storage_usage();
+        context.storage_usage = initial_storage;
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+
+        let used = env::storage_usage() - initial_storage;
+        let record = contract.get_record("troute").unwrap();
+        assert_eq!(record.price, ONE_NEAR - storage_cost(used));
+
+        // Larger content costs more storage, bid must still exceed the previous price
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "much larger content".repeat(10));
+
+        let used = env::storage_usage() - initial_storage;
+        let record = contract.get_record("troute").unwrap();
+        assert_eq!(record.price, 2 * ONE_NEAR - storage_cost(used));
+        assert_eq!(record.owner, "carol");
+    }
+
+    #[test]
+    #[should_panic(expected = "does not cover the storage cost")]
+    fn deposit_below_storage_cost() {
+        let mut context = get_context("alice", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        context.attached_deposit = 1;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough deposit to purchase route")]
+    fn bid_excludes_storage_cost() {
+        let mut context = get_context("alice", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+
+        // Same deposit, but part of it goes to storage so the bid is lower than the price paid
+        context.predecessor_account_id = "carol".to_string();
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+    }
+
+    #[test]
+    fn paginate_routes() {
+        let mut context = get_context("alice", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        assert_eq!(contract.get_num_routes(), 0);
+        assert!(contract.get_routes(0, 10).is_empty());
+
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        for i in 0..5 {
+            contract.purchase(format!("route{}", i), format!("content{}", i));
+        }
+        assert_eq!(contract.get_num_routes(), 5);
+
+        let routes = contract.get_routes(1, 2);
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].route, "route1");
+        assert_eq!(routes[0].content, "content1");
+        assert_eq!(routes[0].owner, "alice");
+        assert_eq!(
+            routes[1].price.0,
+            contract.get_record("route2").unwrap().price
+        );
+
+        // Pages past the end are truncated
+        assert_eq!(contract.get_routes(3, 10).len(), 2);
+        assert!(contract.get_routes(5, 10).is_empty());
+        assert_eq!(contract.get_routes(0, u64::MAX).len(), 5);
+
+        // Replacing a route doesn't change the number of routes
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("route0".to_string(), "new content".to_string());
+        assert_eq!(contract.get_num_routes(), 5);
+        assert!(contract
+            .get_routes(0, 5)
+            .iter()
+            .any(|r| r.route == "route0" && r.owner == "carol"));
+    }
+
+    #[test]
+    fn route_info() {
+        let mut context = get_context("alice", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        assert!(contract.get_route_info("troute".to_string()).is_none());
+
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.content, "tcontent");
+        assert_eq!(info.owner, "alice");
+        assert_eq!(info.price.0, contract.get_record("troute").unwrap().price);
+
+        // Prices are serialized as strings to be safe in JSON
+        let json = near_sdk::serde_json::to_value(&info).unwrap();
+        assert_eq!(json["price"], info.price.0.to_string());
+        assert_eq!(json["owner"], "alice");
+    }
+
+    fn parse_event(log: &str) -> near_sdk::serde_json::Value {
+        let json = log.strip_prefix("EVENT_JSON:").expect("not an event log");
+        let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(json).unwrap();
+        assert_eq!(event["standard"], EVENT_STANDARD);
+        assert_eq!(event["version"], EVENT_VERSION);
+        event
+    }
+
+    #[test]
+    fn event_logs() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_commission(100);
+
+        context.predecessor_account_id = "alice".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+        let price = contract.get_record("troute").unwrap().price;
+
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        let event = parse_event(&logs[0]);
+        assert_eq!(event["event"], "route_purchased");
+        assert_eq!(event["data"]["route"], "troute");
+        assert_eq!(event["data"]["owner"], "alice");
+        assert_eq!(event["data"]["price"], price.to_string());
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "new content".to_string());
+
+        let events: Vec<_> = get_logs().iter().map(|log| parse_event(log)).collect();
+        let names: Vec<_> = events.iter().map(|e| e["event"].clone()).collect();
+        assert_eq!(names, ["route_outbid", "refund_sent", "route_purchased"]);
+        assert_eq!(events[0]["data"]["previous_owner"], "alice");
+        assert_eq!(events[0]["data"]["price"], price.to_string());
+        assert_eq!(events[1]["data"]["account_id"], "alice");
+        assert_eq!(events[2]["data"]["owner"], "carol");
+
+        context.predecessor_account_id = "bob".to_string();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        contract.withdraw();
+
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        let event = parse_event(&logs[0]);
+        assert_eq!(event["event"], "owner_withdraw");
+        assert_eq!(event["data"]["account_id"], "bob");
+        assert_eq!(event["data"]["amount"], (price / 100).to_string());
+    }
+
+    /// Contract with "token.near" accepted for purchases, storage deposits from alice and carol,
+    /// and the context of a call from the token.
+    fn setup_ft() -> (ContentTracker, VMContext) {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_ft_token(Some("token.near".to_string()));
+
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        for account in &["alice", "carol"] {
+            contract.storage_deposit(Some(account.to_string()));
+        }
+
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 0;
+        context.predecessor_account_id = "token.near".to_string();
+        testing_env!(context.clone());
+        (contract, context)
+    }
+
+    fn ft_purchase_msg(route: &str, content: &str) -> String {
+        format!(r#"{{"route": "{}", "content": "{}"}}"#, route, content)
+    }
+
+    #[test]
+    fn ft_purchase_and_replace() {
+        let (mut contract, context) = setup_ft();
+        assert_eq!(contract.get_ft_token(), Some("token.near".to_string()));
+
+        let unused = contract.ft_on_transfer(
+            "alice".to_string(),
+            U128(100),
+            ft_purchase_msg("troute", "tcontent"),
+        );
+        assert!(matches!(unused, PromiseOrValue::Value(U128(0))));
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.content, "tcontent");
+        assert_eq!(info.owner, "alice");
+        assert_eq!(info.price, U128(100));
+        assert_eq!(info.token_id, Some("token.near".to_string()));
+
+        testing_env!(context);
+        contract.ft_on_transfer(
+            "carol".to_string(),
+            U128(150),
+            ft_purchase_msg("troute", "new content"),
+        );
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.owner, "carol");
+        assert_eq!(info.price, U128(150));
+
+        // Previous owner is refunded the full token price
+        let events: Vec<_> = get_logs().iter().map(|log| parse_event(log)).collect();
+        assert_eq!(events[1]["event"], "refund_sent");
+        assert_eq!(events[1]["data"]["account_id"], "alice");
+        assert_eq!(events[1]["data"]["amount"], "100");
+        assert_eq!(events[1]["data"]["token_id"], "token.near");
+    }
+
+    #[test]
+    fn ft_purchase_uses_storage_balance() {
+        let (mut contract, context) = setup_ft();
+        let msg = ft_purchase_msg("troute", "tcontent");
+        contract.ft_on_transfer("alice".to_string(), U128(100), msg.clone());
+        let storage_paid = contract.get_record("troute").unwrap().storage_paid;
+        assert!(storage_paid > 0);
+        assert_eq!(
+            contract.storage_balance_of("alice".to_string()),
+            U128(ONE_NEAR - storage_paid)
+        );
+
+        // Outbid holder's storage paid goes back to their storage balance
+        testing_env!(context);
+        contract.ft_on_transfer("carol".to_string(), U128(150), msg);
+        assert_eq!(
+            contract.storage_balance_of("alice".to_string()),
+            U128(ONE_NEAR)
+        );
+        assert!(contract.storage_balance_of("carol".to_string()).0 < ONE_NEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "call storage_deposit first")]
+    fn ft_purchase_without_storage_balance() {
+        let (mut contract, _) = setup_ft();
+        contract.ft_on_transfer(
+            "dave".to_string(),
+            U128(100),
+            ft_purchase_msg("troute", "tcontent"),
+        );
+    }
+
+    #[test]
+    fn storage_withdraw() {
+        let (mut contract, mut context) = setup_ft();
+        context.predecessor_account_id = "alice".to_string();
+        testing_env!(context);
+        contract.storage_withdraw();
+        assert_eq!(contract.storage_balance_of("alice".to_string()), U128(0));
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["event"], "refund_sent");
+        assert_eq!(event["data"]["amount"], ONE_NEAR.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "needs")]
+    fn ft_update_content_storage_not_covered() {
+        let (mut contract, mut context) = setup_ft();
+        contract.ft_on_transfer("alice".to_string(), U128(1), ft_purchase_msg("troute", "c"));
+
+        // Growing content is paid with an attached deposit like on NEAR-priced routes
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "alice".to_string();
+        testing_env!(context);
+        contract.update_content("troute".to_string(), "x".repeat(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token other.near is not accepted for purchases")]
+    fn ft_purchase_unknown_token() {
+        let (mut contract, mut context) = setup_ft();
+        context.predecessor_account_id = "other.near".to_string();
+        testing_env!(context);
+        contract.ft_on_transfer(
+            "alice".to_string(),
+            U128(100),
+            ft_purchase_msg("troute", "tcontent"),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid purchase msg")]
+    fn ft_purchase_invalid_msg() {
+        let (mut contract, _) = setup_ft();
+        contract.ft_on_transfer("alice".to_string(), U128(100), "troute".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough deposit to purchase route, price: 100 bid: 100")]
+    fn ft_purchase_underbid() {
+        let (mut contract, _) = setup_ft();
+        let msg = ft_purchase_msg("troute", "tcontent");
+        contract.ft_on_transfer("alice".to_string(), U128(100), msg.clone());
+        contract.ft_on_transfer("carol".to_string(), U128(100), msg);
+    }
+
+    #[test]
+    #[should_panic(expected = "Route is priced in token.near, cannot purchase with NEAR")]
+    fn near_purchase_of_token_route() {
+        let (mut contract, mut context) = setup_ft();
+        contract.ft_on_transfer(
+            "alice".to_string(),
+            U128(100),
+            ft_purchase_msg("troute", "tcontent"),
+        );
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+    }
+
+    #[test]
+    fn failed_ft_refund_claimable() {
+        let (mut contract, mut context) = setup_ft();
+        testing_env!(
+            context.clone(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_ft_refund_complete("token.near".to_string(), "carol".to_string(), U128(7));
+        assert_eq!(
+            contract.get_claimable_ft_refund("token.near".to_string(), "carol".to_string()),
+            U128(7)
+        );
+        assert_eq!(
+            contract.get_claimable_ft_refund("other.near".to_string(), "carol".to_string()),
+            U128(0)
+        );
+
+        context.predecessor_account_id = "carol".to_string();
+        testing_env!(context);
+        contract.claim_ft_refund("token.near".to_string());
+        assert_eq!(
+            contract.get_claimable_ft_refund("token.near".to_string(), "carol".to_string()),
+            U128(0)
+        );
+    }
+
+    #[test]
+    fn migrate_initial_state() {
+        let mut context = get_context("alice.near", false);
+        testing_env!(context.clone());
+        let mut old = ContentTrackerV0 {
+            values: LookupMap::new(b"v".to_vec()),
+            contract_owner: "bob".to_string(),
+        };
+        for (route, owner) in &[("route1", "carol"), ("route2", "dave")] {
+            old.values.insert(
+                &route.to_string(),
+                &ContentRecordV0 {
+                    price: ONE_NEAR,
+                    content: "content".to_string(),
+                    owner: owner.to_string(),
+                },
+            );
+        }
+        env::state_write(&old);
+
+        let mut contract = ContentTracker::migrate();
+        assert_eq!(contract.get_owner(), "bob");
+        assert_eq!(
+            env::storage_read(STATE_VERSION_KEY),
+            Some(vec![STATE_VERSION])
+        );
+
+        // Initial routes can't be enumerated, but are still readable
+        assert_eq!(contract.get_num_routes(), 0);
+        let info = contract.get_route_info("route1".to_string()).unwrap();
+        assert_eq!(info.owner, "carol");
+        assert_eq!(info.price, U128(ONE_NEAR));
+
+        // Outbidding an initial route moves it and refunds its price, storage was paid by the
+        // contract
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "erin".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("route1".to_string(), "new content".to_string());
+        let event = parse_event(&get_logs()[1]);
+        assert_eq!(event["event"], "refund_sent");
+        assert_eq!(event["data"]["account_id"], "carol");
+        assert_eq!(event["data"]["amount"], ONE_NEAR.to_string());
+        assert!(contract
+            .legacy_values
+            .as_ref()
+            .unwrap()
+            .get(&"route1".to_string())
+            .is_none());
+
+        // The owner moves the rest so they are listed
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "bob".to_string();
+        context.attached_deposit = 0;
+        testing_env!(context);
+        let routes = vec!["route1".to_string(), "route2".to_string()];
+        assert_eq!(contract.import_legacy_routes(routes), 1);
+        assert_eq!(contract.get_num_routes(), 2);
+        let held = contract.get_routes_for_owner("dave".to_string(), 0, 10);
+        assert_eq!(held[0].route, "route2");
+    }
+
+    #[test]
+    fn migrate_current_state() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_commission(500);
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+        env::state_write(&contract);
+
+        // Upgrades which don't change the layout read the state as is
+        let contract = ContentTracker::migrate();
+        assert_eq!(contract.get_commission(), 500);
+        assert_eq!(
+            contract
+                .get_routes_for_owner("bob".to_string(), 0, 10)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown state version [7]")]
+    fn migrate_unknown_version() {
+        testing_env!(get_context("bob", false));
+        let contract = ContentTracker::new("bob".to_string());
+        env::state_write(&contract);
+        env::storage_write(STATE_VERSION_KEY, &[7]);
+        ContentTracker::migrate();
+    }
+
+    #[test]
+    fn upgrade_by_owner() {
+        let mut context = get_context("bob", false);
+        context.prepaid_gas = 300_000_000_000_000;
+        testing_env!(context);
+        let contract = ContentTracker::new("bob".to_string());
+        contract.upgrade(Base64VecU8(vec![0, 1, 2]));
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn upgrade_not_owner() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let contract = ContentTracker::new("bob".to_string());
+
+        context.predecessor_account_id = "mallory".to_string();
+        testing_env!(context);
+        contract.upgrade(Base64VecU8(vec![0, 1, 2]));
+    }
+
+    /// Contract with "troute" purchased by "alice", and the context of a call from her.
+    fn setup_route() -> (ContentTracker, VMContext) {
+        let mut context = get_context("alice", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        (contract, context)
+    }
+
+    #[test]
+    fn delegated_editing() {
+        let (mut contract, mut context) = setup_route();
+
+        // Owner can update content within the storage paid for
+        contract.update_content("troute".to_string(), "content".to_string());
+        assert_eq!(
+            contract.get_route("troute".to_string()),
+            Some("content".to_string())
+        );
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["event"], "content_updated");
+        assert_eq!(event["data"]["editor"], "alice");
+
+        context.attached_deposit = ONE_NEAR / 100;
+        testing_env!(context.clone());
+        contract.approve_editor("troute".to_string(), "ops.alice".to_string());
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.editor, Some("ops.alice".to_string()));
+
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "ops.alice".to_string();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        contract.update_content("troute".to_string(), "ops content".to_string());
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.content, "ops content");
+        assert_eq!(info.owner, "alice");
+
+        // Editors are cleared when the route changes hands
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.editor, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the route owner or its editor can update content")]
+    fn revoked_editor() {
+        let (mut contract, mut context) = setup_route();
+        context.attached_deposit = ONE_NEAR / 100;
+        testing_env!(context.clone());
+        contract.approve_editor("troute".to_string(), "ops.alice".to_string());
+        contract.revoke_editor("troute".to_string());
+
+        context.attached_deposit = 0;
+        context.predecessor_account_id = "ops.alice".to_string();
+        testing_env!(context);
+        contract.update_content("troute".to_string(), "ops content".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the route owner can approve editors")]
+    fn editor_cannot_approve() {
+        let (mut contract, mut context) = setup_route();
+        context.attached_deposit = ONE_NEAR / 100;
+        testing_env!(context.clone());
+        contract.approve_editor("troute".to_string(), "ops.alice".to_string());
+
+        context.attached_deposit = 0;
+        context.predecessor_account_id = "ops.alice".to_string();
+        testing_env!(context);
+        contract.approve_editor("troute".to_string(), "mallory".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "attach a deposit to cover it")]
+    fn update_content_storage_not_covered() {
+        let (mut contract, _) = setup_route();
+        contract.update_content("troute".to_string(), "much larger content".repeat(10));
+    }
+
+    #[test]
+    fn update_content_with_deposit() {
+        let (mut contract, mut context) = setup_route();
+        let storage_paid = contract.get_record("troute").unwrap().storage_paid;
+
+        context.attached_deposit = ONE_NEAR / 100;
+        testing_env!(context);
+        contract.update_content("troute".to_string(), "much larger content".repeat(10));
+        let record = contract.get_record("troute").unwrap();
+        assert_eq!(record.storage_paid, storage_paid + ONE_NEAR / 100);
+        assert_eq!(record.content, "much larger content".repeat(10));
+    }
+
+    #[test]
+    fn transfer_ownership() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        contract.propose_owner("multisig.near".to_string());
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["event"], "owner_proposed");
+        assert_eq!(event["data"]["owner"], "bob");
+        assert_eq!(event["data"]["proposed_owner"], "multisig.near");
+        // Ownership doesn't change until accepted
+        assert_eq!(contract.get_owner(), "bob");
+
+        context.predecessor_account_id = "multisig.near".to_string();
+        testing_env!(context.clone());
+        contract.accept_owner();
+        assert_eq!(contract.get_owner(), "multisig.near");
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["event"], "owner_changed");
+        assert_eq!(event["data"]["previous_owner"], "bob");
+        assert_eq!(event["data"]["owner"], "multisig.near");
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn previous_owner_loses_access() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.propose_owner("multisig.near".to_string());
+
+        context.predecessor_account_id = "multisig.near".to_string();
+        testing_env!(context.clone());
+        contract.accept_owner();
+
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context);
+        contract.propose_owner("bob".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the proposed owner can accept ownership")]
+    fn accept_owner_not_proposed() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.propose_owner("multisig.near".to_string());
+
+        context.predecessor_account_id = "mallory".to_string();
+        testing_env!(context);
+        contract.accept_owner();
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn propose_owner_not_owner() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+
+        context.predecessor_account_id = "mallory".to_string();
+        testing_env!(context);
+        contract.propose_owner("mallory".to_string());
+    }
+
+    const LEASE: u64 = 1_000_000_000;
+
+    /// Contract with leases enabled at a base price of 0.25 NEAR and "troute" leased by "alice"
+    /// for 1 NEAR.
+    fn setup_lease() -> (ContentTracker, VMContext) {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_lease_duration(Some(U64(LEASE)));
+        contract.set_lease_base_price(U128(ONE_NEAR / 4));
+
+        context.predecessor_account_id = "alice".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        (contract, context)
+    }
+
+    #[test]
+    fn leased_purchase() {
+        let (contract, context) = setup_lease();
+        let info = contract.get_route_info("troute".to_string()).unwrap();
+        assert_eq!(info.expires_at, Some(U64(context.block_timestamp + LEASE)));
+        assert_eq!(contract.get_lease_duration(), Some(U64(LEASE)));
+        assert_eq!(contract.get_lease_base_price(), U128(ONE_NEAR / 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough deposit to purchase route")]
+    fn underbid_active_lease() {
+        let (mut contract, mut context) = setup_lease();
+        context.block_timestamp += LEASE - 1;
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR / 2;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+    }
+
+    #[test]
+    fn purchase_expired_lease() {
+        let (mut contract, mut context) = setup_lease();
+        let record = contract.get_record("troute").unwrap();
+
+        // A bid of the base price takes an expired route, refunding the lapsed holder
+        context.block_timestamp += LEASE;
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR / 2;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "new content".to_string());
+
+        let logs = get_logs();
+        let event = parse_event(&logs[0]);
+        assert_eq!(event["event"], "lease_expired");
+        assert_eq!(event["data"]["previous_owner"], "alice");
+        assert_eq!(event["data"]["price"], record.price.to_string());
+        let event = parse_event(&logs[1]);
+        assert_eq!(event["event"], "refund_sent");
+        assert_eq!(event["data"]["account_id"], "alice");
+        assert_eq!(event["data"]["amount"], ONE_NEAR.to_string());
+
+        let record = contract.get_record("troute").unwrap();
+        assert_eq!(record.owner, "carol");
+        assert_eq!(record.expires_at, Some(context.block_timestamp + LEASE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough deposit to purchase expired route")]
+    fn underbid_expired_lease() {
+        let (mut contract, mut context) = setup_lease();
+        context.block_timestamp += LEASE;
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR / 5;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+    }
+
+    #[test]
+    fn renew_lease() {
+        let (mut contract, mut context) = setup_lease();
+        let record = contract.get_record("troute").unwrap();
+        let expires_at = record.expires_at.unwrap();
+
+        // Renewing before expiry extends from the current expiry, collecting the deposit as a fee
+        context.attached_deposit = ONE_NEAR / 4;
+        testing_env!(context.clone());
+        contract.renew("troute".to_string());
+        let renewed = contract.get_record("troute").unwrap();
+        assert_eq!(renewed.expires_at, Some(expires_at + LEASE));
+        assert_eq!(renewed.price, record.price);
+        assert_eq!(renewed.storage_paid, record.storage_paid);
+        assert_eq!(contract.get_fees_collected(), U128(ONE_NEAR / 4));
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["event"], "lease_renewed");
+        assert_eq!(
+            event["data"]["expires_at"],
+            (expires_at + LEASE).to_string()
+        );
+
+        // Renewing after expiry, before anyone takes the route, extends from now
+        context.block_timestamp = expires_at + 5 * LEASE;
+        testing_env!(context.clone());
+        contract.renew("troute".to_string());
+        let renewed = contract.get_record("troute").unwrap();
+        assert_eq!(renewed.expires_at, Some(context.block_timestamp + LEASE));
+        assert_eq!(contract.get_fees_collected(), U128(ONE_NEAR / 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit of 1 does not cover the lease base price")]
+    fn renew_below_base_price() {
+        let (mut contract, mut context) = setup_lease();
+        context.attached_deposit = 1;
+        testing_env!(context);
+        contract.renew("troute".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Renewals are disabled until a lease base price is set")]
+    fn renew_without_base_price() {
+        let (mut contract, mut context) = setup_lease();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_lease_base_price(U128(0));
+
+        context.predecessor_account_id = "alice".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.renew("troute".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the route owner can renew it")]
+    fn renew_not_owner() {
+        let (mut contract, mut context) = setup_lease();
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.renew("troute".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Route is not leased")]
+    fn renew_unleased_route() {
+        let (mut contract, mut context) = setup_route();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.renew("troute".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease duration can't be more than")]
+    fn lease_duration_too_long() {
+        let (mut contract, mut context) = setup_lease();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context);
+        contract.set_lease_duration(Some(U64(MAX_LEASE_DURATION + 1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease expiry overflowed")]
+    fn renew_past_max_expiry() {
+        let (mut contract, mut context) = setup_lease();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_lease_duration(Some(U64(MAX_LEASE_DURATION)));
+
+        // Each renewal extends the expiry by the longest lease, until it no longer fits
+        context.predecessor_account_id = "alice".to_string();
+        context.attached_deposit = ONE_NEAR / 4;
+        testing_env!(context);
+        for _ in 0..=u64::MAX / MAX_LEASE_DURATION {
+            contract.renew("troute".to_string());
+        }
+    }
+
+    #[test]
+    fn set_limits() {
+        let (mut contract, mut context) = setup_route();
+        assert_eq!(contract.get_limits(), Limits::default());
+        assert_eq!(contract.get_commission(), 0);
+        assert_eq!(contract.get_fees_collected(), U128(0));
+
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_limits(8, 16, Some("abcdefghijklmnopqrstuvwxyz-".to_string()));
+        let limits = contract.get_limits();
+        assert_eq!(limits.max_route_len, 8);
+        assert_eq!(limits.max_content_len, 16);
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("new-rte".to_string(), "within limits".to_string());
+        assert_eq!(
+            contract.get_route("new-rte".to_string()),
+            Some("within limits".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn set_limits_not_owner() {
+        let (mut contract, _) = setup_route();
+        contract.set_limits(8, 16, None);
+    }
+
+    /// Contract with routes limited to 8 lowercase letters and content to 16 bytes, and the
+    /// context of a call from "carol" with 1 NEAR attached.
+    fn setup_limits() -> (ContentTracker, VMContext) {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_limits(8, 16, Some("abcdefghijklmnopqrstuvwxyz".to_string()));
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        (contract, context)
+    }
+
+    #[test]
+    #[should_panic(expected = "Route is 9 bytes long, the limit is 8")]
+    fn route_too_long() {
+        let (mut contract, _) = setup_limits();
+        contract.purchase("ninechars".to_string(), "content".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Route contains '/', only characters in")]
+    fn route_outside_charset() {
+        let (mut contract, _) = setup_limits();
+        contract.purchase("a/b".to_string(), "content".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Content is 17 bytes long, the limit is 16")]
+    fn content_too_long() {
+        let (mut contract, _) = setup_limits();
+        contract.purchase("route".to_string(), "x".repeat(17));
+    }
+
+    #[test]
+    #[should_panic(expected = "Content is 17 bytes long, the limit is 16")]
+    fn update_content_too_long() {
+        let (mut contract, mut context) = setup_limits();
+        contract.purchase("route".to_string(), "content".to_string());
+
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 0;
+        testing_env!(context);
+        contract.update_content("route".to_string(), "x".repeat(17));
+    }
+
+    #[test]
+    fn release_route() {
+        let (mut contract, context) = setup_route();
+        let record = contract.get_record("troute").unwrap();
+        contract.release("troute".to_string());
+
+        assert!(contract.get_route("troute".to_string()).is_none());
+        assert_eq!(contract.get_num_routes(), 0);
+        assert!(context.storage_usage > env::storage_usage());
+
+        let logs = get_logs();
+        let event = parse_event(&logs[0]);
+        assert_eq!(event["event"], "route_released");
+        assert_eq!(event["data"]["owner"], "alice");
+        assert_eq!(event["data"]["price"], record.price.to_string());
+        let event = parse_event(&logs[1]);
+        assert_eq!(event["event"], "refund_sent");
+        assert_eq!(event["data"]["account_id"], "alice");
+        assert_eq!(event["data"]["amount"], ONE_NEAR.to_string());
+    }
+
+    #[test]
+    fn release_ft_route() {
+        let (mut contract, mut context) = setup_ft();
+        contract.ft_on_transfer(
+            "carol".to_string(),
+            U128(100),
+            ft_purchase_msg("troute", "c"),
+        );
+
+        context.predecessor_account_id = "carol".to_string();
+        testing_env!(context);
+        contract.release("troute".to_string());
+        assert!(contract.get_route("troute".to_string()).is_none());
+        let event = parse_event(&get_logs()[1]);
+        assert_eq!(event["event"], "refund_sent");
+        assert_eq!(event["data"]["amount"], "100");
+        assert_eq!(event["data"]["token_id"], "token.near");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the route owner can release it")]
+    fn release_not_owner() {
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "carol".to_string();
+        testing_env!(context);
+        contract.release("troute".to_string());
+    }
+
+    #[test]
+    fn commission_on_outbid() {
+        let mut context = get_context("bob", false);
+        testing_env!(context.clone());
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_commission(250);
+        assert_eq!(contract.get_commission(), 250);
+
+        context.predecessor_account_id = "alice".to_string();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "tcontent".to_string());
+        let record = contract.get_record("troute").unwrap();
+        assert_eq!(record.commission_bps, 250);
+
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "new content".to_string());
+        let fee = record.price * 250 / 10_000;
+        assert_eq!(contract.get_fees_collected(), U128(fee));
+        let event = parse_event(&get_logs()[1]);
+        assert_eq!(event["event"], "refund_sent");
+        assert_eq!(event["data"]["amount"], (ONE_NEAR - fee).to_string());
+
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "bob".to_string();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        contract.withdraw();
+        assert_eq!(contract.get_fees_collected(), U128(0));
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["event"], "owner_withdraw");
+        assert_eq!(event["data"]["amount"], fee.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "No fees to withdraw")]
+    fn withdraw_without_fees() {
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context);
+        contract.withdraw();
+    }
+
+    #[test]
+    fn commission_change_keeps_existing_rate() {
+        // alice bought the route with no commission
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_commission(MAX_COMMISSION_BPS);
+
+        // Outbidding alice refunds her in full
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("troute".to_string(), "new content".to_string());
+        assert_eq!(contract.get_fees_collected(), U128(0));
+        let event = parse_event(&get_logs()[1]);
+        assert_eq!(event["data"]["amount"], ONE_NEAR.to_string());
+        let record = contract.get_record("troute").unwrap();
+        assert_eq!(record.commission_bps, MAX_COMMISSION_BPS);
+
+        // Lowering the rate doesn't lower the commission carol bought the route at
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "bob".to_string();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        contract.set_commission(0);
+
+        context.predecessor_account_id = "dave".to_string();
+        context.attached_deposit = 3 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "dave content".to_string());
+        let fee = commission(record.price, MAX_COMMISSION_BPS);
+        assert_eq!(contract.get_fees_collected(), U128(fee));
+        let event = parse_event(&get_logs()[1]);
+        assert_eq!(event["data"]["amount"], (2 * ONE_NEAR - fee).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Commission of 1001 basis points is over the maximum of 1000")]
+    fn commission_over_max() {
+        let context = get_context("bob", false);
+        testing_env!(context);
+        let mut contract = ContentTracker::new("bob".to_string());
+        contract.set_commission(MAX_COMMISSION_BPS + 1);
+    }
+
+    #[test]
+    fn commission_arithmetic() {
+        assert_eq!(commission(ONE_NEAR, 0), 0);
+        assert_eq!(commission(ONE_NEAR, 100), ONE_NEAR / 100);
+        assert_eq!(commission(ONE_NEAR, 10_000), ONE_NEAR);
+        assert_eq!(commission(9_999, 1), 0);
+        assert_eq!(commission(12_345, 2_500), 3_086);
+        // Prices where `price * bps` would overflow
+        assert_eq!(commission(Balance::MAX, 10_000), Balance::MAX);
+        assert_eq!(commission(Balance::MAX, 5_000), Balance::MAX / 2);
+        assert_eq!(commission(Balance::MAX, 1), Balance::MAX / 10_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fees collected overflowed")]
+    fn fees_collected_overflow() {
+        let (mut contract, mut context) = setup_route();
+        let mut record = contract.get_record("troute").unwrap();
+        record.commission_bps = MAX_COMMISSION_BPS;
+        contract
+            .values
+            .insert(&"troute".to_string(), &VersionedContentRecord::from(record));
+        contract.fees_collected = Balance::MAX;
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+    }
+
+    #[test]
+    fn purchase_many_merges_refunds() {
+        let (mut contract, mut context) = setup_route();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("route2".to_string(), "content2".to_string());
+        let first = contract.get_record("troute").unwrap();
+        let second = contract.get_record("route2").unwrap();
+
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 10 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase_many(vec![
+            ("troute".to_string(), "c1".to_string(), U128(2 * ONE_NEAR)),
+            ("route2".to_string(), "c2".to_string(), U128(3 * ONE_NEAR)),
+            ("route3".to_string(), "c3".to_string(), U128(ONE_NEAR)),
+        ]);
+        for route in &["troute", "route2", "route3"] {
+            assert_eq!(contract.get_record(route).unwrap().owner, "carol");
+        }
+        assert_eq!(contract.get_num_routes(), 3);
+
+        let events: Vec<_> = get_logs().iter().map(|log| parse_event(log)).collect();
+        let refunds: Vec<_> = events
+            .iter()
+            .filter(|e| e["event"] == "refund_sent")
+            .map(|e| e["data"].clone())
+            .collect();
+        assert_eq!(refunds.len(), 2);
+        assert_eq!(refunds[0]["account_id"], "alice");
+        let alice_refund = first.price + first.storage_paid + second.price + second.storage_paid;
+        assert_eq!(refunds[0]["amount"], alice_refund.to_string());
+        assert_eq!(refunds[1]["account_id"], "carol");
+        assert_eq!(refunds[1]["amount"], (4 * ONE_NEAR).to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Bids add up to more than the attached deposit")]
+    fn purchase_many_over_deposit() {
+        let (mut contract, mut context) = setup_route();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        contract.purchase_many(vec![
+            ("route2".to_string(), "c2".to_string(), U128(ONE_NEAR / 2)),
+            (
+                "route3".to_string(),
+                "c3".to_string(),
+                U128(ONE_NEAR / 2 + 1),
+            ),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough deposit to purchase route")]
+    fn purchase_many_underbid() {
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase_many(vec![
+            ("route2".to_string(), "c2".to_string(), U128(ONE_NEAR)),
+            ("troute".to_string(), "c1".to_string(), U128(ONE_NEAR / 2)),
+        ]);
+    }
+
+    #[test]
+    fn routes_for_owner() {
+        let (mut contract, mut context) = setup_route();
+        assert_eq!(
+            contract.get_routes_for_owner("alice".to_string(), 0, 10)[0].route,
+            "troute"
+        );
+        assert!(contract
+            .get_routes_for_owner("carol".to_string(), 0, 10)
+            .is_empty());
+
+        context.attached_deposit = ONE_NEAR;
+        for route in &["route1", "route2"] {
+            testing_env!(context.clone());
+            contract.purchase(route.to_string(), "content".to_string());
+            context.storage_usage = env::storage_usage();
+        }
+        let routes: Vec<_> = contract
+            .get_routes_for_owner("alice".to_string(), 1, 10)
+            .into_iter()
+            .map(|r| r.route)
+            .collect();
+        assert_eq!(routes, ["route1", "route2"]);
+
+        // Routes move to the index of the new owner when they change hands
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("route1".to_string(), "carol".to_string());
+        let routes = contract.get_routes_for_owner("carol".to_string(), 0, 10);
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].route, "route1");
+        assert_eq!(routes[0].owner, "carol");
+        let routes: Vec<_> = contract
+            .get_routes_for_owner("alice".to_string(), 0, 10)
+            .into_iter()
+            .map(|r| r.route)
+            .collect();
+        assert_eq!(routes, ["troute", "route2"]);
+        assert_eq!(
+            contract
+                .get_routes_for_owner("alice".to_string(), 1, 1)
+                .len(),
+            1
+        );
+
+        // Released routes are removed, and accounts holding none are dropped from the index
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 0;
+        testing_env!(context);
+        contract.release("route1".to_string());
+        assert!(contract
+            .get_routes_for_owner("carol".to_string(), 0, 10)
+            .is_empty());
+        assert!(contract.routes_by_owner.get(&"carol".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Purchases are paused")]
+    fn purchase_while_paused() {
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_paused(true);
+        assert!(contract.is_paused());
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Purchases are paused")]
+    fn ft_purchase_while_paused() {
+        let (mut contract, mut context) = setup_ft();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_paused(true);
+
+        context.predecessor_account_id = "token.near".to_string();
+        testing_env!(context);
+        contract.ft_on_transfer(
+            "carol".to_string(),
+            U128(100),
+            ft_purchase_msg("troute", "c"),
+        );
+    }
+
+    #[test]
+    fn unpause() {
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context.clone());
+        contract.set_paused(true);
+        contract.set_paused(false);
+
+        context.predecessor_account_id = "carol".to_string();
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        contract.purchase("troute".to_string(), "new content".to_string());
+        assert_eq!(contract.get_record("troute").unwrap().owner, "carol");
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn set_paused_not_owner() {
+        let (mut contract, _) = setup_route();
+        contract.set_paused(true);
+    }
+
+    #[test]
+    fn wind_down_refunds_holders() {
+        let (mut contract, mut context) = setup_route();
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        contract.purchase("route2".to_string(), "content".to_string());
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "carol".to_string();
+        testing_env!(context.clone());
+        contract.purchase("route3".to_string(), "content".to_string());
+
+        context.storage_usage = env::storage_usage();
+        context.predecessor_account_id = "bob".to_string();
+        context.attached_deposit = 0;
+        testing_env!(context.clone());
+        contract.set_paused(true);
+        assert_eq!(contract.wind_down(0, 2), 1);
+        let refunds: Vec<_> = get_logs().iter().map(|log| parse_event(log)).collect();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0]["event"], "refund_sent");
+        assert_eq!(refunds[0]["data"]["account_id"], "alice");
+        assert_eq!(refunds[0]["data"]["amount"], (2 * ONE_NEAR).to_string());
+
+        context.storage_usage = env::storage_usage();
+        testing_env!(context);
+        assert_eq!(contract.wind_down(0, 2), 0);
+        let event = parse_event(&get_logs()[0]);
+        assert_eq!(event["data"]["account_id"], "carol");
+        assert_eq!(event["data"]["amount"], ONE_NEAR.to_string());
+        assert_eq!(contract.get_num_routes(), 0);
+        assert!(contract.routes_by_owner.get(&"alice".to_string()).is_none());
+        assert!(contract.routes_by_owner.get(&"carol".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Pause the contract before winding down")]
+    fn wind_down_not_paused() {
+        let (mut contract, mut context) = setup_route();
+        context.predecessor_account_id = "bob".to_string();
+        testing_env!(context);
+        contract.wind_down(0, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "left == right")]
+    fn wind_down_not_owner() {
+        let (mut contract, _) = setup_route();
+        contract.wind_down(0, 10);
     }
 }
@@ -0,0 +1,1087 @@
+//! Local stand-in for a NEAR sandbox, executing compiled contracts with `near-vm-logic` on the
+//! `wasmi` interpreter. Receipts created by contracts are executed until none are left, so
+//! transfers move real balances and callbacks receive the results of the promises they wait on.
+//!
+//! This is a test harness, not a faithful runtime, and only covers what the contract uses:
+//!
+//! - Only the host functions the contract imports are linked, see `HOST_FUNCTIONS`.
+//! - Gas is not charged to signers, so balances only move through deposits and transfers. Gas
+//!   limits are not enforced either.
+//! - Receipts run one at a time as soon as the receipts they depend on have run, all within the
+//!   block of the transaction. Actions other than creating accounts, deploying code, function
+//!   calls and transfers fail the receipt.
+//!
+//! The one piece of `unsafe` is `SharedMemory`, which lends the interpreter's memory to
+//! `VMLogic` for the duration of each host function call.
+
+use near_primitives_core::runtime::fees::RuntimeFeesConfig;
+use near_vm_logic::types::{PromiseResult, ReceiptIndex};
+use near_vm_logic::{
+    External, HostError, MemoryLike, ReturnData, VMConfig, VMContext, VMLogic, VMLogicError,
+    ValuePtr,
+};
+use serde::de::DeserializeOwned;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use wasmi::{Caller, Engine, Func, Linker, Memory, Module, Store, Val};
+
+pub type AccountId = String;
+pub type Balance = u128;
+pub type Gas = u64;
+
+/// One NEAR in yocto.
+pub const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+/// Cost of storing one byte of account state.
+pub const STORAGE_PRICE_PER_BYTE: Balance = 10_000_000_000_000_000_000;
+
+/// Gas attached to transactions.
+pub const DEFAULT_GAS: Gas = 300_000_000_000_000;
+
+/// Storage every account uses for its own record and access key.
+const ACCOUNT_STORAGE_USAGE: u64 = 182;
+
+/// Nanoseconds between blocks, each transaction is executed in a new block.
+const BLOCK_TIME: u64 = 1_000_000_000;
+
+/// Account with its balance and, if a contract is deployed, its code and state.
+#[derive(Clone, Default)]
+pub struct Account {
+    pub balance: Balance,
+    pub storage_usage: u64,
+    code: Option<Rc<Module>>,
+    state: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+/// Result of a transaction and the receipts it spawned.
+#[derive(Debug)]
+pub struct ExecutionResult {
+    /// Value returned by the called method, after resolving any promise it returned.
+    pub status: Result<Vec<u8>, String>,
+    /// Logs from every receipt executed.
+    pub logs: Vec<String>,
+    /// Errors of receipts which failed, including the called method.
+    pub failures: Vec<String>,
+}
+
+impl ExecutionResult {
+    pub fn is_ok(&self) -> bool {
+        self.status.is_ok()
+    }
+
+    /// Panics if the called method or any receipt it spawned failed.
+    pub fn assert_success(&self) {
+        assert!(
+            self.status.is_ok() && self.failures.is_empty(),
+            "Transaction failed: {:?}",
+            self.failures
+        );
+    }
+
+    /// Panics unless the called method failed with an error containing `msg`.
+    pub fn assert_failure(&self, msg: &str) {
+        match &self.status {
+            Err(err) => assert!(
+                err.contains(msg),
+                "Expected failure containing {:?}, got {:?}",
+                msg,
+                err
+            ),
+            Ok(_) => panic!("Expected failure containing {:?}, but succeeded", msg),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Action {
+    CreateAccount,
+    DeployContract(Vec<u8>),
+    FunctionCall {
+        method_name: String,
+        args: Vec<u8>,
+        deposit: Balance,
+        gas: Gas,
+    },
+    Transfer(Balance),
+    Unsupported(&'static str),
+}
+
+impl Action {
+    fn deposit(&self) -> Balance {
+        match self {
+            Action::FunctionCall { deposit, .. } => *deposit,
+            Action::Transfer(deposit) => *deposit,
+            _ => 0,
+        }
+    }
+}
+
+struct Receipt {
+    id: u64,
+    predecessor_id: AccountId,
+    signer_id: AccountId,
+    receiver_id: AccountId,
+    actions: Vec<Action>,
+    /// Receipts whose results are passed to the function call in this receipt.
+    dependencies: Vec<u64>,
+}
+
+/// Result of an executed receipt, passed to callbacks waiting on it.
+#[derive(Clone)]
+enum ReceiptResult {
+    Successful(Vec<u8>),
+    Failed,
+}
+
+impl From<ReceiptResult> for PromiseResult {
+    fn from(result: ReceiptResult) -> Self {
+        match result {
+            ReceiptResult::Successful(value) => PromiseResult::Successful(value),
+            ReceiptResult::Failed => PromiseResult::Failed,
+        }
+    }
+}
+
+/// Outcome of a receipt, which may be forwarded to the outcome of a promise it returned.
+enum ReceiptOutcome {
+    Resolved(ReceiptResult),
+    Forwarded(u64),
+}
+
+/// Local NEAR runtime holding accounts and executing transactions against them.
+pub struct Sandbox {
+    accounts: HashMap<AccountId, Account>,
+    block_index: u64,
+    block_timestamp: u64,
+    next_receipt_id: u64,
+    engine: Engine,
+    config: VMConfig,
+    fees_config: RuntimeFeesConfig,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Self {
+            accounts: HashMap::new(),
+            block_index: 1,
+            block_timestamp: 1_600_000_000_000_000_000,
+            next_receipt_id: 0,
+            engine: Engine::default(),
+            config: VMConfig::default(),
+            fees_config: RuntimeFeesConfig::default(),
+        }
+    }
+
+    /// Creates an account with the given balance.
+    pub fn create_account(&mut self, account_id: &str, balance: Balance) {
+        assert!(
+            !self.accounts.contains_key(account_id),
+            "Account {} already exists",
+            account_id
+        );
+        self.accounts.insert(
+            account_id.to_string(),
+            Account {
+                balance,
+                storage_usage: ACCOUNT_STORAGE_USAGE,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Creates an account with the given balance and deploys the contract code to it.
+    pub fn deploy(&mut self, account_id: &str, code: &[u8], balance: Balance) {
+        self.create_account(account_id, balance);
+        let account = self.accounts.get_mut(account_id).unwrap();
+        deploy_code(&self.engine, account, code).unwrap();
+    }
+
+    /// Removes the account, along with its balance and state.
+    pub fn delete_account(&mut self, account_id: &str) {
+        self.accounts
+            .remove(account_id)
+            .unwrap_or_else(|| panic!("Account {} doesn't exist", account_id));
+    }
+
+    pub fn account(&self, account_id: &str) -> Option<&Account> {
+        self.accounts.get(account_id)
+    }
+
+    /// Balance of the account, panics if it doesn't exist.
+    pub fn balance(&self, account_id: &str) -> Balance {
+        self.accounts
+            .get(account_id)
+            .unwrap_or_else(|| panic!("Account {} doesn't exist", account_id))
+            .balance
+    }
+
+    /// Timestamp of the next block, in nanoseconds.
+    pub fn block_timestamp(&self) -> u64 {
+        self.block_timestamp
+    }
+
+    /// Moves the clock forward by the given number of nanoseconds.
+    pub fn advance_time(&mut self, nanoseconds: u64) {
+        self.block_timestamp += nanoseconds;
+    }
+
+    /// Calls a method with JSON arguments, attaching a deposit from the signer, and executes all
+    /// receipts spawned by the call.
+    pub fn call(
+        &mut self,
+        signer_id: &str,
+        receiver_id: &str,
+        method_name: &str,
+        args: serde_json::Value,
+        deposit: Balance,
+    ) -> ExecutionResult {
+        let signer = self
+            .accounts
+            .get_mut(signer_id)
+            .unwrap_or_else(|| panic!("Signer {} doesn't exist", signer_id));
+        assert!(
+            signer.balance >= deposit,
+            "Signer {} can't cover deposit of {}",
+            signer_id,
+            deposit
+        );
+        signer.balance -= deposit;
+
+        let id = self.new_receipt_id();
+        let receipt = Receipt {
+            id,
+            predecessor_id: signer_id.to_string(),
+            signer_id: signer_id.to_string(),
+            receiver_id: receiver_id.to_string(),
+            actions: vec![Action::FunctionCall {
+                method_name: method_name.to_string(),
+                args: args.to_string().into_bytes(),
+                deposit,
+                gas: DEFAULT_GAS,
+            }],
+            dependencies: Vec::new(),
+        };
+        let result = self.execute(receipt);
+        self.block_index += 1;
+        self.block_timestamp += BLOCK_TIME;
+        result
+    }
+
+    /// Calls a view method with JSON arguments and deserializes the JSON result.
+    pub fn view<T: DeserializeOwned>(
+        &self,
+        account_id: &str,
+        method_name: &str,
+        args: serde_json::Value,
+    ) -> T {
+        let mut account = self
+            .accounts
+            .get(account_id)
+            .unwrap_or_else(|| panic!("Account {} doesn't exist", account_id))
+            .clone();
+        let context = self.context(account_id, account_id, account_id, &account, 0, true);
+        let outcome = self
+            .run(
+                &mut account,
+                context,
+                method_name,
+                args.to_string().into_bytes(),
+                &[],
+            )
+            .unwrap_or_else(|err| panic!("View call failed: {}", err));
+        match outcome.return_data {
+            ReturnData::Value(value) => {
+                serde_json::from_slice(&value).expect("Return value is not valid JSON")
+            }
+            _ => panic!("View call didn't return a value"),
+        }
+    }
+
+    fn new_receipt_id(&mut self) -> u64 {
+        self.next_receipt_id += 1;
+        self.next_receipt_id
+    }
+
+    /// Executes the receipt and all receipts spawned by it, in the order they become ready.
+    fn execute(&mut self, receipt: Receipt) -> ExecutionResult {
+        let root = receipt.id;
+        let mut pending = vec![receipt];
+        let mut outcomes: HashMap<u64, ReceiptOutcome> = HashMap::new();
+        let mut logs = Vec::new();
+        let mut failures = Vec::new();
+
+        while !pending.is_empty() {
+            let ready = pending
+                .iter()
+                .position(|r| {
+                    r.dependencies
+                        .iter()
+                        .all(|id| resolve(&outcomes, *id).is_some())
+                })
+                .expect("Receipts are waiting on each other");
+            let receipt = pending.remove(ready);
+            let promise_results: Vec<PromiseResult> = receipt
+                .dependencies
+                .iter()
+                .map(|id| resolve(&outcomes, *id).unwrap().into())
+                .collect();
+
+            let applied = self.apply(&receipt, &promise_results);
+            logs.extend(applied.logs);
+            let outcome = match applied.result {
+                Ok((outcome, spawned)) => {
+                    pending.extend(spawned);
+                    outcome
+                }
+                Err(err) => {
+                    failures.push(err);
+                    // Deposits of failed receipts are refunded to the predecessor
+                    let refund: Balance = receipt.actions.iter().map(Action::deposit).sum();
+                    if let Some(predecessor) = self.accounts.get_mut(&receipt.predecessor_id) {
+                        predecessor.balance += refund;
+                    }
+                    ReceiptOutcome::Resolved(ReceiptResult::Failed)
+                }
+            };
+            outcomes.insert(receipt.id, outcome);
+        }
+
+        let status = match resolve(&outcomes, root) {
+            Some(ReceiptResult::Successful(value)) => Ok(value),
+            _ => Err(failures
+                .first()
+                .cloned()
+                .unwrap_or_else(|| "Receipt failed".to_string())),
+        };
+        ExecutionResult {
+            status,
+            logs,
+            failures,
+        }
+    }
+
+    /// Applies the actions of a receipt to its receiver, reverting them all if any fails.
+    fn apply(&mut self, receipt: &Receipt, promise_results: &[PromiseResult]) -> AppliedReceipt {
+        let mut applied = AppliedReceipt {
+            logs: Vec::new(),
+            result: Ok((
+                ReceiptOutcome::Resolved(ReceiptResult::Successful(Vec::new())),
+                Vec::new(),
+            )),
+        };
+        let mut account = self.accounts.get(&receipt.receiver_id).cloned();
+        let mut spawned = Vec::new();
+        let mut outcome = ReceiptOutcome::Resolved(ReceiptResult::Successful(Vec::new()));
+
+        for action in &receipt.actions {
+            let result = match action {
+                Action::CreateAccount => match account {
+                    Some(_) => Err(format!("Account {} already exists", receipt.receiver_id)),
+                    None => {
+                        account = Some(Account {
+                            storage_usage: ACCOUNT_STORAGE_USAGE,
+                            ..Default::default()
+                        });
+                        Ok(())
+                    }
+                },
+                _ if account.is_none() => {
+                    Err(format!("Account {} doesn't exist", receipt.receiver_id))
+                }
+                Action::DeployContract(code) => {
+                    deploy_code(&self.engine, account.as_mut().unwrap(), code)
+                }
+                Action::Transfer(amount) => {
+                    account.as_mut().unwrap().balance += amount;
+                    Ok(())
+                }
+                Action::FunctionCall {
+                    method_name,
+                    args,
+                    deposit,
+                    gas,
+                } => {
+                    let account = account.as_mut().unwrap();
+                    let mut context = self.context(
+                        &receipt.receiver_id,
+                        &receipt.signer_id,
+                        &receipt.predecessor_id,
+                        account,
+                        *deposit,
+                        false,
+                    );
+                    context.prepaid_gas = *gas;
+                    let run =
+                        self.run(account, context, method_name, args.clone(), promise_results);
+                    match run {
+                        Ok(run) => {
+                            applied.logs.extend(run.logs);
+                            let ids: Vec<u64> =
+                                run.receipts.iter().map(|_| self.new_receipt_id()).collect();
+                            for (receipt_index, created) in run.receipts.into_iter().enumerate() {
+                                spawned.push(Receipt {
+                                    id: ids[receipt_index],
+                                    predecessor_id: receipt.receiver_id.clone(),
+                                    signer_id: receipt.signer_id.clone(),
+                                    receiver_id: created.receiver_id,
+                                    actions: created.actions,
+                                    dependencies: created
+                                        .receipt_indices
+                                        .iter()
+                                        .map(|index| ids[*index as usize])
+                                        .collect(),
+                                });
+                            }
+                            outcome = match run.return_data {
+                                ReturnData::Value(value) => {
+                                    ReceiptOutcome::Resolved(ReceiptResult::Successful(value))
+                                }
+                                ReturnData::ReceiptIndex(index) => {
+                                    ReceiptOutcome::Forwarded(ids[index as usize])
+                                }
+                                ReturnData::None => {
+                                    ReceiptOutcome::Resolved(ReceiptResult::Successful(Vec::new()))
+                                }
+                            };
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                Action::Unsupported(name) => Err(format!("{} actions are not supported", name)),
+            };
+            if let Err(err) = result {
+                applied.result = Err(err);
+                return applied;
+            }
+        }
+
+        let account = account.unwrap();
+        let required = Balance::from(account.storage_usage) * STORAGE_PRICE_PER_BYTE;
+        if account.balance < required {
+            applied.result = Err(format!(
+                "LackBalanceForState: {} needs {} to cover storage but has {}",
+                receipt.receiver_id, required, account.balance
+            ));
+            return applied;
+        }
+        self.accounts.insert(receipt.receiver_id.clone(), account);
+        applied.result = Ok((outcome, spawned));
+        applied
+    }
+
+    fn context(
+        &self,
+        account_id: &str,
+        signer_id: &str,
+        predecessor_id: &str,
+        account: &Account,
+        attached_deposit: Balance,
+        is_view: bool,
+    ) -> VMContext {
+        VMContext {
+            current_account_id: account_id.to_string(),
+            signer_account_id: signer_id.to_string(),
+            signer_account_pk: vec![0; 33],
+            predecessor_account_id: predecessor_id.to_string(),
+            input: Vec::new(),
+            block_index: self.block_index,
+            block_timestamp: self.block_timestamp,
+            epoch_height: 1,
+            account_balance: account.balance,
+            account_locked_balance: 0,
+            storage_usage: account.storage_usage,
+            attached_deposit,
+            prepaid_gas: DEFAULT_GAS,
+            random_seed: vec![0; 32],
+            is_view,
+            output_data_receivers: Vec::new(),
+        }
+    }
+
+    /// Runs a method of the contract deployed to the account, updating the account if it
+    /// succeeds.
+    fn run(
+        &self,
+        account: &mut Account,
+        mut context: VMContext,
+        method_name: &str,
+        input: Vec<u8>,
+        promise_results: &[PromiseResult],
+    ) -> Result<RunOutcome, String> {
+        let module = account
+            .code
+            .as_ref()
+            .ok_or_else(|| format!("No contract deployed to {}", context.current_account_id))?;
+        context.input = input;
+
+        let mut ext = SimExternal {
+            state: account.state.clone(),
+            receipts: Vec::new(),
+        };
+        let memory = SharedMemory::default();
+        let mut logic_memory = memory.clone();
+        let logic = VMLogic::new(
+            &mut ext,
+            context,
+            &self.config,
+            &self.fees_config,
+            promise_results,
+            &mut logic_memory,
+            None,
+        );
+        let mut store = Store::new(
+            &self.engine,
+            HostState {
+                logic,
+                memory: None,
+                shared: memory,
+            },
+        );
+        let result = self.invoke(module, method_name, &mut store);
+        let outcome = store.into_data().logic.outcome();
+        if let Err(err) = result {
+            return Err(match err.downcast_ref::<SimHostError>() {
+                Some(SimHostError(VMLogicError::HostError(HostError::GuestPanic {
+                    panic_msg,
+                }))) => {
+                    format!("Smart contract panicked: {}", panic_msg)
+                }
+                Some(host) => host.to_string(),
+                None => err.to_string(),
+            });
+        }
+
+        account.balance = outcome.balance;
+        account.storage_usage = outcome.storage_usage;
+        account.state = ext.state;
+        Ok(RunOutcome {
+            return_data: outcome.return_data,
+            logs: outcome.logs,
+            receipts: ext.receipts,
+        })
+    }
+
+    /// Instantiates the module with host functions bound to the store's `VMLogic` and calls the
+    /// method. Contracts must export their memory, as near-sdk contracts do.
+    fn invoke(
+        &self,
+        module: &Module,
+        method_name: &str,
+        store: &mut Store<HostState>,
+    ) -> Result<(), wasmi::Error> {
+        let mut linker = Linker::new(&self.engine);
+        for import in module.imports() {
+            let name = HOST_FUNCTIONS
+                .iter()
+                .find(|name| import.module() == "env" && **name == import.name())
+                .copied()
+                .ok_or_else(|| {
+                    wasmi::Error::new(format!(
+                        "Unsupported import {}.{}",
+                        import.module(),
+                        import.name()
+                    ))
+                })?;
+            let ty = import
+                .ty()
+                .func()
+                .ok_or_else(|| wasmi::Error::new(format!("Import {} is not a function", name)))?;
+            let func = Func::new(&mut *store, ty.clone(), move |caller, args, results| {
+                call_host(caller, name, args, results)
+            });
+            linker.define("env", name, func)?;
+        }
+
+        let instance = linker
+            .instantiate(&mut *store, module)?
+            .ensure_no_start(&mut *store)?;
+        let memory = instance
+            .get_memory(&*store, "memory")
+            .ok_or_else(|| wasmi::Error::new("Contract doesn't export its memory"))?;
+        store.data_mut().memory = Some(memory);
+        let func = instance
+            .get_func(&store, method_name)
+            .ok_or_else(|| wasmi::Error::new(format!("Method {} not found", method_name)))?;
+        func.call(store, &[], &mut [])
+    }
+}
+
+struct AppliedReceipt {
+    logs: Vec<String>,
+    result: Result<(ReceiptOutcome, Vec<Receipt>), String>,
+}
+
+struct RunOutcome {
+    return_data: ReturnData,
+    logs: Vec<String>,
+    receipts: Vec<CreatedReceipt>,
+}
+
+/// Follows forwarded outcomes to the result of the receipt, if it has been executed.
+fn resolve(outcomes: &HashMap<u64, ReceiptOutcome>, id: u64) -> Option<ReceiptResult> {
+    match outcomes.get(&id)? {
+        ReceiptOutcome::Resolved(result) => Some(result.clone()),
+        ReceiptOutcome::Forwarded(id) => resolve(outcomes, *id),
+    }
+}
+
+fn deploy_code(engine: &Engine, account: &mut Account, code: &[u8]) -> Result<(), String> {
+    let module = Module::new(engine, code).map_err(|err| format!("Invalid contract: {}", err))?;
+    account.code = Some(Rc::new(module));
+    account.storage_usage += code.len() as u64;
+    Ok(())
+}
+
+/// Receipt created by a contract, with dependencies as indices of other receipts it created.
+struct CreatedReceipt {
+    receipt_indices: Vec<ReceiptIndex>,
+    receiver_id: AccountId,
+    actions: Vec<Action>,
+}
+
+/// Contract state and receipts created while running a method.
+struct SimExternal {
+    state: HashMap<Vec<u8>, Vec<u8>>,
+    receipts: Vec<CreatedReceipt>,
+}
+
+impl SimExternal {
+    fn push_action(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        action: Action,
+    ) -> Result<(), VMLogicError> {
+        self.receipts[receipt_index as usize].actions.push(action);
+        Ok(())
+    }
+}
+
+struct SimValuePtr(Vec<u8>);
+
+impl ValuePtr for SimValuePtr {
+    fn len(&self) -> u32 {
+        self.0.len() as u32
+    }
+
+    fn deref(&self) -> Result<Vec<u8>, VMLogicError> {
+        Ok(self.0.clone())
+    }
+}
+
+impl External for SimExternal {
+    fn storage_set(&mut self, key: &[u8], value: &[u8]) -> Result<(), VMLogicError> {
+        self.state.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn storage_get<'a>(
+        &'a self,
+        key: &[u8],
+    ) -> Result<Option<Box<dyn ValuePtr + 'a>>, VMLogicError> {
+        Ok(self
+            .state
+            .get(key)
+            .map(|value| Box::new(SimValuePtr(value.clone())) as Box<_>))
+    }
+
+    fn storage_remove(&mut self, key: &[u8]) -> Result<(), VMLogicError> {
+        self.state.remove(key);
+        Ok(())
+    }
+
+    fn storage_remove_subtree(&mut self, prefix: &[u8]) -> Result<(), VMLogicError> {
+        self.state.retain(|key, _| !key.starts_with(prefix));
+        Ok(())
+    }
+
+    fn storage_has_key(&mut self, key: &[u8]) -> Result<bool, VMLogicError> {
+        Ok(self.state.contains_key(key))
+    }
+
+    fn create_receipt(
+        &mut self,
+        receipt_indices: Vec<ReceiptIndex>,
+        receiver_id: AccountId,
+    ) -> Result<ReceiptIndex, VMLogicError> {
+        self.receipts.push(CreatedReceipt {
+            receipt_indices,
+            receiver_id,
+            actions: Vec::new(),
+        });
+        Ok(self.receipts.len() as u64 - 1)
+    }
+
+    fn append_action_create_account(
+        &mut self,
+        receipt_index: ReceiptIndex,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::CreateAccount)
+    }
+
+    fn append_action_deploy_contract(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        code: Vec<u8>,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::DeployContract(code))
+    }
+
+    fn append_action_function_call(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        method_name: Vec<u8>,
+        arguments: Vec<u8>,
+        attached_deposit: Balance,
+        prepaid_gas: Gas,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(
+            receipt_index,
+            Action::FunctionCall {
+                method_name: String::from_utf8_lossy(&method_name).into_owned(),
+                args: arguments,
+                deposit: attached_deposit,
+                gas: prepaid_gas,
+            },
+        )
+    }
+
+    fn append_action_transfer(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        amount: Balance,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::Transfer(amount))
+    }
+
+    fn append_action_stake(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        _stake: Balance,
+        _public_key: Vec<u8>,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::Unsupported("Stake"))
+    }
+
+    fn append_action_add_key_with_full_access(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        _public_key: Vec<u8>,
+        _nonce: u64,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::Unsupported("AddKey"))
+    }
+
+    fn append_action_add_key_with_function_call(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        _public_key: Vec<u8>,
+        _nonce: u64,
+        _allowance: Option<Balance>,
+        _receiver_id: AccountId,
+        _method_names: Vec<Vec<u8>>,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::Unsupported("AddKey"))
+    }
+
+    fn append_action_delete_key(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        _public_key: Vec<u8>,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::Unsupported("DeleteKey"))
+    }
+
+    fn append_action_delete_account(
+        &mut self,
+        receipt_index: ReceiptIndex,
+        _beneficiary_id: AccountId,
+    ) -> Result<(), VMLogicError> {
+        self.push_action(receipt_index, Action::Unsupported("DeleteAccount"))
+    }
+
+    fn get_touched_nodes_count(&self) -> u64 {
+        0
+    }
+
+    fn reset_touched_nodes_counter(&mut self) {}
+
+    fn validator_stake(&self, _account_id: &AccountId) -> Result<Option<Balance>, VMLogicError> {
+        Ok(None)
+    }
+
+    fn validator_total_stake(&self) -> Result<Balance, VMLogicError> {
+        Ok(0)
+    }
+}
+
+/// Contract memory as seen by `VMLogic`, which keeps a clone for its whole lifetime. It points at
+/// the interpreter's memory only while a host function is called, and is empty otherwise.
+///
+/// Safety relies on these invariants, upheld by [`SharedMemory::lend`] and `call_host`:
+///
+/// - The pointer is only set by `lend`, from the `&mut [u8]` wasmi hands out for the memory
+///   during a host function call, and is cleared when the returned guard drops, including on
+///   unwinding. The slice isn't touched through any other path while the pointer is set.
+/// - Host functions never call back into the interpreter, so the memory can't grow or move
+///   while it's lent.
+/// - Slices returned by `data` and `data_mut` don't outlive the `VMLogic` call they're made in,
+///   and `VMLogic` doesn't hold on to them.
+#[derive(Clone, Default)]
+struct SharedMemory(Rc<Cell<(*mut u8, usize)>>);
+
+/// Clears the pointer of a [`SharedMemory`] when dropped.
+struct LentMemory<'a>(&'a SharedMemory);
+
+impl Drop for LentMemory<'_> {
+    fn drop(&mut self) {
+        self.0 .0.set((std::ptr::null_mut(), 0));
+    }
+}
+
+impl SharedMemory {
+    /// Points at the interpreter's memory until the returned guard is dropped.
+    fn lend<'a>(&'a self, data: &'a mut [u8]) -> LentMemory<'a> {
+        self.0.set((data.as_mut_ptr(), data.len()));
+        LentMemory(self)
+    }
+
+    fn data(&self) -> &[u8] {
+        let (ptr, len) = self.0.get();
+        if ptr.is_null() {
+            return &[];
+        }
+        // SAFETY: non-null only while lent, pointing at `len` initialized bytes, see above
+        unsafe { std::slice::from_raw_parts(ptr, len) }
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        let (ptr, len) = self.0.get();
+        if ptr.is_null() {
+            return &mut [];
+        }
+        // SAFETY: as in `data`, and the lent slice was mutable
+        unsafe { std::slice::from_raw_parts_mut(ptr, len) }
+    }
+
+    fn range(&self, offset: u64, len: usize) -> std::ops::Range<usize> {
+        let start = offset as usize;
+        start..start + len
+    }
+}
+
+impl MemoryLike for SharedMemory {
+    fn fits_memory(&self, offset: u64, len: u64) -> bool {
+        let size = self.0.get().1 as u64;
+        offset.checked_add(len).is_some_and(|end| end <= size)
+    }
+
+    fn read_memory(&self, offset: u64, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.data()[self.range(offset, buffer.len())]);
+    }
+
+    fn read_memory_u8(&self, offset: u64) -> u8 {
+        self.data()[offset as usize]
+    }
+
+    fn write_memory(&mut self, offset: u64, buffer: &[u8]) {
+        let range = self.range(offset, buffer.len());
+        self.data_mut()[range].copy_from_slice(buffer);
+    }
+}
+
+/// Data of the interpreter store, giving host functions access to `VMLogic` and memory.
+struct HostState<'a> {
+    logic: VMLogic<'a>,
+    /// Memory exported by the contract, set once it's instantiated.
+    memory: Option<Memory>,
+    /// Clone of the memory `logic` reads and writes through.
+    shared: SharedMemory,
+}
+
+#[derive(Debug)]
+struct SimHostError(VMLogicError);
+
+impl fmt::Display for SimHostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl wasmi::core::HostError for SimHostError {}
+
+/// Host functions available to contracts, in the `env` module. These are the ones the contract
+/// imports, other imports fail instantiation.
+const HOST_FUNCTIONS: &[&str] = &[
+    "read_register",
+    "register_len",
+    "current_account_id",
+    "signer_account_id",
+    "signer_account_pk",
+    "predecessor_account_id",
+    "input",
+    "block_index",
+    "block_timestamp",
+    "epoch_height",
+    "storage_usage",
+    "account_balance",
+    "account_locked_balance",
+    "attached_deposit",
+    "prepaid_gas",
+    "used_gas",
+    "random_seed",
+    "sha256",
+    "keccak256",
+    "keccak512",
+    "validator_stake",
+    "validator_total_stake",
+    "value_return",
+    "panic",
+    "panic_utf8",
+    "log_utf8",
+    "log_utf16",
+    "promise_create",
+    "promise_then",
+    "promise_and",
+    "promise_batch_create",
+    "promise_batch_then",
+    "promise_batch_action_create_account",
+    "promise_batch_action_deploy_contract",
+    "promise_batch_action_function_call",
+    "promise_batch_action_transfer",
+    "promise_batch_action_stake",
+    "promise_batch_action_add_key_with_full_access",
+    "promise_batch_action_add_key_with_function_call",
+    "promise_batch_action_delete_key",
+    "promise_batch_action_delete_account",
+    "promise_results_count",
+    "promise_result",
+    "promise_return",
+    "storage_write",
+    "storage_read",
+    "storage_remove",
+    "storage_has_key",
+];
+
+/// Dispatches a host function call from the interpreter to `VMLogic`, lending it the contract's
+/// memory for the call.
+fn call_host(
+    mut caller: Caller<'_, HostState>,
+    name: &str,
+    args: &[Val],
+    results: &mut [Val],
+) -> Result<(), wasmi::Error> {
+    let memory = caller
+        .data()
+        .memory
+        .ok_or_else(|| wasmi::Error::new("Contract memory is not set"))?;
+    let (data, state) = memory.data_and_store_mut(&mut caller);
+    let lent = state.shared.lend(data);
+    let result = dispatch(&mut state.logic, name, args);
+    drop(lent);
+
+    if let Some(value) = result.map_err(|err| wasmi::Error::host(SimHostError(err)))? {
+        results[0] = Val::I64(value as i64);
+    }
+    Ok(())
+}
+
+fn dispatch(logic: &mut VMLogic, name: &str, args: &[Val]) -> Result<Option<u64>, VMLogicError> {
+    let a = |i: usize| match args[i] {
+        Val::I64(value) => value as u64,
+        Val::I32(value) => value as u32 as u64,
+        _ => 0,
+    };
+    let unit = |r: Result<(), VMLogicError>| r.map(|_| None);
+    let value = |r: Result<u64, VMLogicError>| r.map(Some);
+
+    match name {
+        "read_register" => unit(logic.read_register(a(0), a(1))),
+        "register_len" => value(logic.register_len(a(0))),
+        "current_account_id" => unit(logic.current_account_id(a(0))),
+        "signer_account_id" => unit(logic.signer_account_id(a(0))),
+        "signer_account_pk" => unit(logic.signer_account_pk(a(0))),
+        "predecessor_account_id" => unit(logic.predecessor_account_id(a(0))),
+        "input" => unit(logic.input(a(0))),
+        "block_index" => value(logic.block_index()),
+        "block_timestamp" => value(logic.block_timestamp()),
+        "epoch_height" => value(logic.epoch_height()),
+        "storage_usage" => value(logic.storage_usage()),
+        "account_balance" => unit(logic.account_balance(a(0))),
+        "account_locked_balance" => unit(logic.account_locked_balance(a(0))),
+        "attached_deposit" => unit(logic.attached_deposit(a(0))),
+        "prepaid_gas" => value(logic.prepaid_gas()),
+        "used_gas" => value(logic.used_gas()),
+        "random_seed" => unit(logic.random_seed(a(0))),
+        "sha256" => unit(logic.sha256(a(0), a(1), a(2))),
+        "keccak256" => unit(logic.keccak256(a(0), a(1), a(2))),
+        "keccak512" => unit(logic.keccak512(a(0), a(1), a(2))),
+        "validator_stake" => unit(logic.validator_stake(a(0), a(1), a(2))),
+        "validator_total_stake" => unit(logic.validator_total_stake(a(0))),
+        "value_return" => unit(logic.value_return(a(0), a(1))),
+        "panic" => unit(logic.panic()),
+        "panic_utf8" => unit(logic.panic_utf8(a(0), a(1))),
+        "log_utf8" => unit(logic.log_utf8(a(0), a(1))),
+        "log_utf16" => unit(logic.log_utf16(a(0), a(1))),
+        "promise_create" => {
+            value(logic.promise_create(a(0), a(1), a(2), a(3), a(4), a(5), a(6), a(7)))
+        }
+        "promise_then" => {
+            value(logic.promise_then(a(0), a(1), a(2), a(3), a(4), a(5), a(6), a(7), a(8)))
+        }
+        "promise_and" => value(logic.promise_and(a(0), a(1))),
+        "promise_batch_create" => value(logic.promise_batch_create(a(0), a(1))),
+        "promise_batch_then" => value(logic.promise_batch_then(a(0), a(1), a(2))),
+        "promise_batch_action_create_account" => {
+            unit(logic.promise_batch_action_create_account(a(0)))
+        }
+        "promise_batch_action_deploy_contract" => {
+            unit(logic.promise_batch_action_deploy_contract(a(0), a(1), a(2)))
+        }
+        "promise_batch_action_function_call" => {
+            unit(logic.promise_batch_action_function_call(a(0), a(1), a(2), a(3), a(4), a(5), a(6)))
+        }
+        "promise_batch_action_transfer" => unit(logic.promise_batch_action_transfer(a(0), a(1))),
+        "promise_batch_action_stake" => {
+            unit(logic.promise_batch_action_stake(a(0), a(1), a(2), a(3)))
+        }
+        "promise_batch_action_add_key_with_full_access" => {
+            unit(logic.promise_batch_action_add_key_with_full_access(a(0), a(1), a(2), a(3)))
+        }
+        "promise_batch_action_add_key_with_function_call" => {
+            unit(logic.promise_batch_action_add_key_with_function_call(
+                a(0),
+                a(1),
+                a(2),
+                a(3),
+                a(4),
+                a(5),
+                a(6),
+                a(7),
+                a(8),
+            ))
+        }
+        "promise_batch_action_delete_key" => {
+            unit(logic.promise_batch_action_delete_key(a(0), a(1), a(2)))
+        }
+        "promise_batch_action_delete_account" => {
+            unit(logic.promise_batch_action_delete_account(a(0), a(1), a(2)))
+        }
+        "promise_results_count" => value(logic.promise_results_count()),
+        "promise_result" => value(logic.promise_result(a(0), a(1))),
+        "promise_return" => unit(logic.promise_return(a(0))),
+        "storage_write" => value(logic.storage_write(a(0), a(1), a(2), a(3), a(4))),
+        "storage_read" => value(logic.storage_read(a(0), a(1), a(2))),
+        "storage_remove" => value(logic.storage_remove(a(0), a(1), a(2))),
+        "storage_has_key" => value(logic.storage_has_key(a(0), a(1))),
+        name => unreachable!("Unknown host function {}", name),
+    }
+}
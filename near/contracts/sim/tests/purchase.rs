@@ -0,0 +1,362 @@
+use near_plutocratic_hosting_sim::{Balance, Sandbox, ONE_NEAR};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+const CONTRACT: &str = "tracker.near";
+const OWNER: &str = "owner.near";
+
+/// Compiled contract, built once for all tests.
+static CONTRACT_WASM: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Builds the contract on first use and returns the compiled wasm.
+fn contract_wasm() -> &'static [u8] {
+    CONTRACT_WASM.get_or_init(|| {
+        let contract_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../rust");
+        let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+            .args(["build", "--target", "wasm32-unknown-unknown", "--release"])
+            .current_dir(&contract_dir)
+            .status()
+            .expect("Failed to run cargo");
+        assert!(status.success(), "Failed to build contract");
+        std::fs::read(
+            contract_dir
+                .join("target/wasm32-unknown-unknown/release/near_plutocratic_hosting.wasm"),
+        )
+        .unwrap()
+    })
+}
+
+/// Sandbox with the contract deployed and initialized, and accounts for alice and bob.
+fn setup() -> Sandbox {
+    let mut sandbox = Sandbox::new();
+    sandbox.deploy(CONTRACT, contract_wasm(), 10 * ONE_NEAR);
+    for account in &[OWNER, "alice.near", "bob.near", "carol.near"] {
+        sandbox.create_account(account, 100 * ONE_NEAR);
+    }
+    sandbox
+        .call(OWNER, CONTRACT, "new", json!({ "owner_id": OWNER }), 0)
+        .assert_success();
+    sandbox
+}
+
+fn purchase(sandbox: &mut Sandbox, account: &str, content: &str, deposit: Balance) -> Value {
+    let result = sandbox.call(
+        account,
+        CONTRACT,
+        "purchase",
+        json!({ "route": "troute", "content": content }),
+        deposit,
+    );
+    result.assert_success();
+    sandbox.view(CONTRACT, "get_route_info", json!({ "route": "troute" }))
+}
+
+fn price(info: &Value) -> Balance {
+    info["price"].as_str().unwrap().parse().unwrap()
+}
+
+#[test]
+fn purchase_outbid_withdraw() {
+    let mut sandbox = setup();
+    sandbox
+        .call(
+            OWNER,
+            CONTRACT,
+            "set_commission",
+            json!({ "commission_bps": 500 }),
+            0,
+        )
+        .assert_success();
+    let contract_balance = sandbox.balance(CONTRACT);
+
+    let info = purchase(&mut sandbox, "alice.near", "tcontent", ONE_NEAR);
+    assert_eq!(info["owner"], "alice.near");
+    let alice_price = price(&info);
+    assert!(alice_price < ONE_NEAR);
+    assert_eq!(sandbox.balance("alice.near"), 99 * ONE_NEAR);
+    assert_eq!(sandbox.balance(CONTRACT), contract_balance + ONE_NEAR);
+
+    // Outbidding refunds the full deposit, including storage, to the previous owner less the
+    // commission on the price
+    let fee = alice_price / 20;
+    let info = purchase(&mut sandbox, "bob.near", "new content", 2 * ONE_NEAR);
+    assert_eq!(info["owner"], "bob.near");
+    assert_eq!(info["content"], "new content");
+    assert_eq!(sandbox.balance("alice.near"), 100 * ONE_NEAR - fee);
+    assert_eq!(sandbox.balance("bob.near"), 98 * ONE_NEAR);
+    assert_eq!(
+        sandbox.balance(CONTRACT),
+        contract_balance + 2 * ONE_NEAR + fee
+    );
+
+    // Owner withdraws only the commission, bob's deposit stays in the contract
+    sandbox
+        .call(OWNER, CONTRACT, "withdraw", json!({}), 0)
+        .assert_success();
+    assert_eq!(sandbox.balance(OWNER), 100 * ONE_NEAR + fee);
+    assert_eq!(sandbox.balance(CONTRACT), contract_balance + 2 * ONE_NEAR);
+    let fees: String = sandbox.view(CONTRACT, "get_fees_collected", json!({}));
+    assert_eq!(fees, "0");
+
+    // Non-owners can't withdraw
+    sandbox
+        .call("alice.near", CONTRACT, "withdraw", json!({}), 0)
+        .assert_failure("assertion `left == right` failed");
+}
+
+#[test]
+fn zero_deposit_fails() {
+    let mut sandbox = setup();
+    let result = sandbox.call(
+        "alice.near",
+        CONTRACT,
+        "purchase",
+        json!({ "route": "troute", "content": "tcontent" }),
+        0,
+    );
+    result.assert_failure("assertion failed: deposit > 0");
+
+    let info: Value = sandbox.view(CONTRACT, "get_route_info", json!({ "route": "troute" }));
+    assert!(info.is_null());
+    assert_eq!(sandbox.balance("alice.near"), 100 * ONE_NEAR);
+}
+
+#[test]
+fn non_payable_rejects_deposit() {
+    let mut sandbox = setup();
+    let result = sandbox.call(
+        OWNER,
+        CONTRACT,
+        "set_paused",
+        json!({ "paused": true }),
+        ONE_NEAR,
+    );
+    result.assert_failure("Method set_paused doesn't accept deposit");
+
+    // The deposit is refunded and the contract isn't paused
+    assert_eq!(sandbox.balance(OWNER), 100 * ONE_NEAR);
+    let paused: bool = sandbox.view(CONTRACT, "is_paused", json!({}));
+    assert!(!paused);
+}
+
+#[test]
+fn underbid_fails() {
+    let mut sandbox = setup();
+    purchase(&mut sandbox, "alice.near", "tcontent", 2 * ONE_NEAR);
+
+    let result = sandbox.call(
+        "bob.near",
+        CONTRACT,
+        "purchase",
+        json!({ "route": "troute", "content": "new content" }),
+        ONE_NEAR,
+    );
+    result.assert_failure("Not enough deposit to purchase route");
+
+    // Failed purchase deposit is refunded and the route is unchanged
+    assert_eq!(sandbox.balance("bob.near"), 100 * ONE_NEAR);
+    let info: Value = sandbox.view(CONTRACT, "get_route_info", json!({ "route": "troute" }));
+    assert_eq!(info["owner"], "alice.near");
+    assert_eq!(info["content"], "tcontent");
+}
+
+#[test]
+fn failed_refund_is_claimable() {
+    let mut sandbox = setup();
+    let info = purchase(&mut sandbox, "alice.near", "tcontent", ONE_NEAR);
+    let refund = ONE_NEAR;
+    assert!(price(&info) < refund);
+
+    // Refund transfer to a deleted account fails and is credited instead
+    sandbox.delete_account("alice.near");
+    let result = sandbox.call(
+        "bob.near",
+        CONTRACT,
+        "purchase",
+        json!({ "route": "troute", "content": "new content" }),
+        2 * ONE_NEAR,
+    );
+    assert!(result.is_ok());
+    assert_eq!(result.failures.len(), 1);
+    let claimable: String = sandbox.view(
+        CONTRACT,
+        "get_claimable_refund",
+        json!({ "account_id": "alice.near" }),
+    );
+    assert_eq!(claimable, refund.to_string());
+
+    sandbox.create_account("alice.near", 0);
+    sandbox
+        .call("alice.near", CONTRACT, "claim_refund", json!({}), 0)
+        .assert_success();
+    assert_eq!(sandbox.balance("alice.near"), refund);
+    let claimable: String = sandbox.view(
+        CONTRACT,
+        "get_claimable_refund",
+        json!({ "account_id": "alice.near" }),
+    );
+    assert_eq!(claimable, "0");
+}
+
+#[test]
+fn expired_lease_refunds_holder() {
+    let mut sandbox = setup();
+    let lease: u64 = 60_000_000_000;
+    sandbox
+        .call(
+            OWNER,
+            CONTRACT,
+            "set_lease_duration",
+            json!({ "duration": lease.to_string() }),
+            0,
+        )
+        .assert_success();
+    sandbox
+        .call(
+            OWNER,
+            CONTRACT,
+            "set_lease_base_price",
+            json!({ "base_price": (ONE_NEAR / 2).to_string() }),
+            0,
+        )
+        .assert_success();
+    purchase(&mut sandbox, "alice.near", "tcontent", 2 * ONE_NEAR);
+
+    // An expired route goes to a lower bid of at least the base price, with the lapsed holder's
+    // deposit returned
+    sandbox.advance_time(lease);
+    let result = sandbox.call(
+        "carol.near",
+        CONTRACT,
+        "purchase",
+        json!({ "route": "troute", "content": "new content" }),
+        ONE_NEAR / 4,
+    );
+    result.assert_failure("Not enough deposit to purchase expired route");
+    let purchased_at = sandbox.block_timestamp();
+    let info = purchase(&mut sandbox, "bob.near", "new content", ONE_NEAR);
+    assert_eq!(info["owner"], "bob.near");
+    assert_eq!(sandbox.balance("alice.near"), 100 * ONE_NEAR);
+    assert_eq!(sandbox.balance("bob.near"), 99 * ONE_NEAR);
+    assert_eq!(info["expires_at"], (purchased_at + lease).to_string());
+}
+
+#[test]
+fn release_returns_deposit() {
+    let mut sandbox = setup();
+    let contract_balance = sandbox.balance(CONTRACT);
+    let storage_usage = sandbox.account(CONTRACT).unwrap().storage_usage;
+    purchase(&mut sandbox, "alice.near", "tcontent", ONE_NEAR);
+
+    sandbox
+        .call(
+            "alice.near",
+            CONTRACT,
+            "release",
+            json!({ "route": "troute" }),
+            0,
+        )
+        .assert_success();
+    let info: Value = sandbox.view(CONTRACT, "get_route_info", json!({ "route": "troute" }));
+    assert!(info.is_null());
+    assert_eq!(sandbox.balance("alice.near"), 100 * ONE_NEAR);
+    assert_eq!(sandbox.balance(CONTRACT), contract_balance);
+    assert_eq!(
+        sandbox.account(CONTRACT).unwrap().storage_usage,
+        storage_usage
+    );
+}
+
+#[test]
+fn purchase_many_refunds_once() {
+    let mut sandbox = setup();
+    for route in &["route1", "route2"] {
+        sandbox
+            .call(
+                "alice.near",
+                CONTRACT,
+                "purchase",
+                json!({ "route": route, "content": "alice" }),
+                ONE_NEAR,
+            )
+            .assert_success();
+    }
+    let contract_balance = sandbox.balance(CONTRACT);
+
+    let bid = (2 * ONE_NEAR).to_string();
+    let result = sandbox.call(
+        "bob.near",
+        CONTRACT,
+        "purchase_many",
+        json!({ "items": [["route1", "bob", bid], ["route2", "bob", bid]] }),
+        5 * ONE_NEAR,
+    );
+    result.assert_success();
+    let refunds = result
+        .logs
+        .iter()
+        .filter(|log| log.contains("\"refund_sent\""))
+        .count();
+    assert_eq!(refunds, 2);
+
+    // Alice gets both deposits back in one transfer, and bob the deposit he didn't bid
+    assert_eq!(sandbox.balance("alice.near"), 100 * ONE_NEAR);
+    assert_eq!(sandbox.balance("bob.near"), 96 * ONE_NEAR);
+    assert_eq!(sandbox.balance(CONTRACT), contract_balance + 2 * ONE_NEAR);
+    let routes: Value = sandbox.view(
+        CONTRACT,
+        "get_routes",
+        json!({ "from_index": 0, "limit": 2 }),
+    );
+    assert_eq!(routes[0]["owner"], "bob.near");
+    assert_eq!(routes[1]["owner"], "bob.near");
+}
+
+#[test]
+fn wind_down_refunds_everyone() {
+    let mut sandbox = setup();
+    let contract_balance = sandbox.balance(CONTRACT);
+    for (account, route) in &[("alice.near", "route1"), ("bob.near", "route2")] {
+        sandbox
+            .call(
+                account,
+                CONTRACT,
+                "purchase",
+                json!({ "route": route, "content": "content" }),
+                ONE_NEAR,
+            )
+            .assert_success();
+    }
+
+    sandbox
+        .call(OWNER, CONTRACT, "set_paused", json!({ "paused": true }), 0)
+        .assert_success();
+    sandbox
+        .call(
+            "carol.near",
+            CONTRACT,
+            "purchase",
+            json!({ "route": "route3", "content": "content" }),
+            ONE_NEAR,
+        )
+        .assert_failure("Purchases are paused");
+
+    // Every holder gets their deposit back and no routes are left
+    sandbox
+        .call(
+            OWNER,
+            CONTRACT,
+            "wind_down",
+            json!({ "from_index": 0, "limit": 10 }),
+            0,
+        )
+        .assert_success();
+    assert_eq!(sandbox.balance("alice.near"), 100 * ONE_NEAR);
+    assert_eq!(sandbox.balance("bob.near"), 100 * ONE_NEAR);
+    assert_eq!(sandbox.balance("carol.near"), 100 * ONE_NEAR);
+    assert_eq!(sandbox.balance(CONTRACT), contract_balance);
+    let routes: u64 = sandbox.view(CONTRACT, "get_num_routes", json!({}));
+    assert_eq!(routes, 0);
+}